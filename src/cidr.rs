@@ -0,0 +1,114 @@
+use std::io::net::ip::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::num::Int;
+
+
+// Network address with prefix length like 10.0.0.0/8 or 2001:db8::/32
+#[deriving(Clone, PartialEq, Show)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: uint,
+}
+
+impl Cidr {
+    // Parse network, a single address is parsed as /32 or /128 network
+    pub fn parse(cidr: &str) -> Option<Cidr> {
+        let (str_addr, str_prefix) = match cidr.find('/') {
+            Some(pos) => (cidr.slice_to(pos), Some(cidr.slice_from(pos + 1))),
+            None => (cidr, None)
+        };
+        let addr = match str_addr.parse::<IpAddr>() {
+            Some(addr) => addr,
+            None => return None
+        };
+        let max_prefix = address_bits(&addr);
+        let prefix = match str_prefix {
+            Some(str_prefix) => match str_prefix.parse::<uint>() {
+                Some(prefix) if prefix <= max_prefix => prefix,
+                _ => return None
+            },
+            None => max_prefix
+        };
+        Some(Cidr{network: network(&addr, prefix), prefix: prefix})
+    }
+
+    #[inline]
+    pub fn contains(&self, addr: &IpAddr) -> bool {
+        address_bits(addr) == address_bits(&self.network)
+            && network(addr, self.prefix) == self.network
+    }
+}
+
+#[inline]
+fn address_bits(addr: &IpAddr) -> uint {
+    match *addr {
+        Ipv4Addr(..) => 32,
+        Ipv6Addr(..) => 128
+    }
+}
+
+// Network address of the address with the given prefix length
+pub fn network(addr: &IpAddr, prefix: uint) -> IpAddr {
+    match *addr {
+        Ipv4Addr(a, b, c, d) => {
+            let mut octets = [a, b, c, d];
+            mask(&mut octets, prefix);
+            Ipv4Addr(octets[0], octets[1], octets[2], octets[3])
+        },
+        Ipv6Addr(a, b, c, d, e, f, g, h) => {
+            let mut segments = [a, b, c, d, e, f, g, h];
+            mask(&mut segments, prefix);
+            Ipv6Addr(segments[0], segments[1], segments[2], segments[3],
+                     segments[4], segments[5], segments[6], segments[7])
+        }
+    }
+}
+
+// Clear all bits after the prefix
+#[inline]
+fn mask<T: Int>(parts: &mut [T], prefix: uint) {
+    let bits = Int::zero().count_zeros();
+    for (i, part) in parts.iter_mut().enumerate() {
+        let start = i * bits;
+        if prefix <= start {
+            *part = Int::zero();
+        } else if prefix < start + bits {
+            let keep = prefix - start;
+            *part = (*part >> (bits - keep)) << (bits - keep);
+        }
+    }
+}
+
+/*
+ * Tests
+ */
+#[cfg(test)]
+mod test {
+    use super::Cidr;
+
+    #[inline]
+    fn contains(cidr: &str, addr: &str) -> bool {
+        Cidr::parse(cidr).unwrap().contains(&addr.parse().unwrap())
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Cidr::parse("10.1.2.3/8"), Cidr::parse("10.0.0.0/8"));
+        assert!(Cidr::parse("10.0.0.0/33").is_none());
+        assert!(Cidr::parse("10.0.0/8").is_none());
+        assert!(Cidr::parse("2001:db8::/129").is_none());
+        assert!(Cidr::parse("-").is_none());
+    }
+
+    #[test]
+    fn test_contains() {
+        assert!(contains("10.0.0.0/8", "10.255.1.2"));
+        assert!(!contains("10.0.0.0/8", "11.0.0.1"));
+        assert!(contains("192.168.1.0/25", "192.168.1.127"));
+        assert!(!contains("192.168.1.0/25", "192.168.1.128"));
+        assert!(contains("192.168.1.1", "192.168.1.1"));
+        assert!(contains("0.0.0.0/0", "8.8.8.8"));
+        assert!(contains("2001:db8::/32", "2001:db8:1::1"));
+        assert!(!contains("2001:db8::/32", "2001:db9::1"));
+        assert!(!contains("0.0.0.0/0", "::1"));
+    }
+}
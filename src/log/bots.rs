@@ -0,0 +1,215 @@
+use regex::Regex;
+
+use stats::topk::TopK;
+use timezone::timespec;
+use useragent::CRAWLERS;
+
+use super::{HTTPLogRecord, LogFilter, FilterResult};
+use super::content::Category;
+
+
+// Automated HTTP clients at the start of user agents
+static TOOLS: &'static str =
+    "^(curl|Wget|python-requests|Go-http-client|Java|libwww-perl|\
+     Apache-HttpClient|okhttp|Scrapy)";
+// Names for bots detected only by behaviour
+static ROBOTS_TXT_BOT: &'static str = "Unknown (robots.txt)";
+static RATE_BOT: &'static str = "Unknown (page rate)";
+static NO_ASSETS_BOT: &'static str = "Unknown (no assets)";
+// Window and maximal number of pages requested in it
+static RATE_WINDOW: i64 = 60;
+static MAX_RATE: u64 = 60;
+// Maximal number of pages requested without loading any assets
+static MAX_PAGES_WITHOUT_ASSETS: u64 = 10;
+// Number of the most active clients to keep behaviour for
+static CLIENTS_CAPACITY: uint = 100000;
+
+// Traffic accepted by the filter
+#[deriving(Clone, Copy, PartialEq)]
+pub enum Traffic {
+    All,
+    Human,
+    Bot,
+}
+
+impl Traffic {
+    pub fn from_name(name: &str) -> Option<Traffic> {
+        match name {
+            "all" => Some(Traffic::All),
+            "human" => Some(Traffic::Human),
+            "bot" => Some(Traffic::Bot),
+            _ => None
+        }
+    }
+}
+
+impl LogFilter for Traffic {
+    #[inline]
+    fn filter(&mut self, record: &HTTPLogRecord) -> FilterResult {
+        match (*self, record.bot) {
+            (Traffic::Human, Some(_)) | (Traffic::Bot, None) =>
+                FilterResult::Skip,
+            _ => FilterResult::Accept
+        }
+    }
+}
+
+// Behaviour of a client identified by address and user agent
+struct ClientState {
+    bot: Option<&'static str>,
+    pages: u64,
+    assets: u64,
+    window_start: i64,
+    window_pages: u64,
+}
+
+// Detects bots by user agents or by behaviour: robots.txt fetches, high page
+// rate or pages requested without assets. The parser runs it on every record
+// before filters, so filtered out requests still count for the behaviour.
+// Records are marked while streaming, so requests of a client before its
+// behaviour is detected stay human. Only the most active clients are
+// tracked, a client replacing an evicted one starts with a clean state.
+pub struct BotDetector {
+    crawlers: Regex,
+    tools: Regex,
+    clients: TopK<String, ClientState>,
+}
+
+impl BotDetector {
+    pub fn new() -> BotDetector {
+        BotDetector{
+            crawlers: Regex::new(CRAWLERS).unwrap(),
+            tools: Regex::new(TOOLS).unwrap(),
+            clients: TopK::new(CLIENTS_CAPACITY),
+            }
+    }
+
+    #[inline]
+    pub fn detect<'r>(&mut self, record: &HTTPLogRecord<'r>)
+            -> Option<&'r str> {
+        for regex in [&self.crawlers, &self.tools].iter() {
+            match regex.find(record.user_agent) {
+                Some((start, end)) =>
+                    return Some(record.user_agent.slice(start, end)),
+                None => {}
+            }
+        }
+        let time = timespec(&record.local_time).sec;
+        let key = format!("{} {}", record.remote_addr, record.user_agent);
+        let state = self.clients.update(key, ClientState{
+            bot: None,
+            pages: 0,
+            assets: 0,
+            window_start: time,
+            window_pages: 0,
+            });
+        if time - state.window_start >= RATE_WINDOW {
+            state.window_start = time;
+            state.window_pages = 0;
+        }
+        // Only page views count for rules without assets, API and other
+        // clients don't load assets anyway
+        match Category::new(record.path) {
+            Category::Page if record.method == "GET" => {
+                state.pages += 1;
+                state.window_pages += 1;
+            },
+            Category::Static | Category::Image | Category::Media
+                | Category::Font => state.assets += 1,
+            _ => {}
+        }
+        // Page rate doesn't depend on assets, browsers don't request so
+        // many pages either way
+        let detected = if record.path == "/robots.txt" {
+            Some(ROBOTS_TXT_BOT)
+        } else if state.window_pages > MAX_RATE {
+            Some(RATE_BOT)
+        } else if state.assets == 0
+                && state.pages > MAX_PAGES_WITHOUT_ASSETS {
+            Some(NO_ASSETS_BOT)
+        } else {
+            None
+        };
+        // Detected bots stay bots, stronger rules override pages without
+        // assets
+        if detected.is_some()
+                && (state.bot.is_none() || state.bot == Some(NO_ASSETS_BOT)) {
+            state.bot = detected;
+        }
+        state.bot
+    }
+}
+
+/*
+ * Tests
+ */
+#[cfg(test)]
+mod test {
+    use time::{Timespec, at_utc};
+
+    use log::{HTTPLogRecord, test_record};
+
+    use super::{BotDetector, NO_ASSETS_BOT, RATE_BOT};
+
+    fn record<'r>(path: &'r str, user_agent: &'r str, sec: i64)
+            -> HTTPLogRecord<'r> {
+        HTTPLogRecord{
+            remote_addr: "10.0.0.1",
+            local_time: at_utc(Timespec::new(sec, 0)),
+            path: path,
+            user_agent: user_agent,
+            ..test_record()
+            }
+    }
+
+    fn detect(paths: &[&str], user_agent: &str, interval: i64)
+            -> Vec<Option<String>> {
+        let mut detector = BotDetector::new();
+        paths.iter().enumerate().map(|(i, path)| {
+            let time = i as i64 * interval;
+            detector.detect(&record(*path, user_agent, time))
+                .map(|name| name.to_string())
+            }).collect()
+    }
+
+    #[test]
+    fn test_user_agent() {
+        let bots = detect(&["/"], "Mozilla/5.0 (compatible; Googlebot/2.1)",
+                          10);
+        assert_eq!(vec![Some("Googlebot".to_string())], bots);
+    }
+
+    #[test]
+    fn test_pages_without_assets() {
+        // Streaming detection marks pages only after the threshold
+        let pages: Vec<&str> = range(0u, 12).map(|_| "/page").collect();
+        let bots = detect(pages.as_slice(), "Mozilla/5.0", 10);
+        assert!(bots.slice_to(10).iter().all(|bot| bot.is_none()));
+        assert_eq!(Some(NO_ASSETS_BOT.to_string()), bots[10]);
+        assert_eq!(Some(NO_ASSETS_BOT.to_string()), bots[11]);
+        // Clients loading assets and API clients stay human
+        let mut paths = vec!["/style.css"];
+        paths.extend(pages.iter().map(|path| *path));
+        let bots = detect(paths.as_slice(), "Mozilla/5.0", 10);
+        assert!(bots.iter().all(|bot| bot.is_none()));
+        let calls: Vec<&str> = range(0u, 20).map(|_| "/api/items").collect();
+        let bots = detect(calls.as_slice(), "Mozilla/5.0", 10);
+        assert!(bots.iter().all(|bot| bot.is_none()));
+    }
+
+    #[test]
+    fn test_page_rate() {
+        // Loading assets doesn't hide a high page rate
+        let mut paths = vec!["/style.css"];
+        paths.extend(range(0u, 70).map(|_| "/page"));
+        let bots = detect(paths.as_slice(), "Mozilla/5.0", 0);
+        assert!(bots.slice_to(61).iter().all(|bot| bot.is_none()));
+        assert_eq!(Some(RATE_BOT.to_string()), bots[61]);
+        assert_eq!(Some(RATE_BOT.to_string()), bots[70]);
+        // Rate overrides pages without assets
+        let pages: Vec<&str> = range(0u, 70).map(|_| "/page").collect();
+        let bots = detect(pages.as_slice(), "Mozilla/5.0", 0);
+        assert_eq!(Some(NO_ASSETS_BOT.to_string()), bots[59]);
+        assert_eq!(Some(RATE_BOT.to_string()), bots[60]);
+    }
+}
@@ -0,0 +1,140 @@
+use std::ascii::AsciiExt;
+use std::fmt::{Show, Formatter, Result};
+
+
+// Maximal length of a known looking extension
+static MAX_EXTENSION_LENGTH: uint = 8;
+
+// Content category derived from the path
+#[deriving(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Category {
+    Page,
+    Api,
+    Static,
+    Image,
+    Media,
+    Font,
+    Download,
+    Other,
+}
+
+static EXTENSIONS: [(&'static [&'static str], Category), ..7] = [
+    (&["html", "htm", "xhtml", "php", "asp", "aspx", "jsp", "cgi", "shtml"],
+     Category::Page),
+    (&["json", "xml", "rss", "atom"], Category::Api),
+    (&["css", "js", "map", "txt", "mjs"], Category::Static),
+    (&["png", "jpg", "jpeg", "gif", "svg", "webp", "ico", "bmp", "avif",
+       "tif", "tiff"], Category::Image),
+    (&["mp4", "webm", "mp3", "ogg", "ogv", "wav", "m4a", "m4v", "avi", "mov",
+       "flv", "mkv", "ts", "m3u8"], Category::Media),
+    (&["woff", "woff2", "ttf", "otf", "eot"], Category::Font),
+    (&["zip", "gz", "tgz", "bz2", "xz", "rar", "7z", "tar", "pdf", "exe",
+       "dmg", "msi", "deb", "rpm", "apk", "iso"], Category::Download),
+    ];
+
+impl Category {
+    // Paths under /api are API requests regardless of the extension and
+    // paths without extension are pages
+    pub fn new(path: &str) -> Category {
+        if path == "/api" || path.starts_with("/api/")
+                || path.starts_with("/api?") {
+            return Category::Api;
+        }
+        let ext = extension(path);
+        if ext.is_empty() {
+            return Category::Page;
+        }
+        for &(extensions, category) in EXTENSIONS.iter() {
+            if extensions.contains(&ext.as_slice()) {
+                return category;
+            }
+        }
+        Category::Other
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Category::Page => "page",
+            Category::Api => "api",
+            Category::Static => "static",
+            Category::Image => "image",
+            Category::Media => "media",
+            Category::Font => "font",
+            Category::Download => "download",
+            Category::Other => "other"
+        }
+    }
+
+    // Assets which are usually embedded into pages
+    #[inline]
+    pub fn is_embedded(&self) -> bool {
+        match *self {
+            Category::Image | Category::Media | Category::Font => true,
+            _ => false
+        }
+    }
+}
+
+impl Show for Category {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        f.pad(self.name())
+    }
+}
+
+// Lower case extension of the last path segment, empty if there is no
+// extension or it doesn't look like one
+pub fn extension(path: &str) -> String {
+    let path = match path.find('?') {
+        Some(pos) => path.slice_to(pos),
+        None => path
+    };
+    let name = match path.rfind('/') {
+        Some(pos) => path.slice_from(pos + 1),
+        None => path
+    };
+    match name.rfind('.') {
+        Some(pos) if pos > 0 => {
+            let ext = name.slice_from(pos + 1);
+            if ext.is_empty() || ext.len() > MAX_EXTENSION_LENGTH
+                    || !ext.chars().all(|c| c.is_alphanumeric()) {
+                String::new()
+            } else {
+                ext.to_ascii_lower()
+            }
+        },
+        _ => String::new()
+    }
+}
+
+/*
+ * Tests
+ */
+#[cfg(test)]
+mod test {
+    use super::{Category, extension};
+
+    #[test]
+    fn test_extension() {
+        assert_eq!("html", extension("/index.HTML").as_slice());
+        assert_eq!("js", extension("/static/app.min.js?v=123").as_slice());
+        assert_eq!("", extension("/users/1").as_slice());
+        assert_eq!("", extension("/v1.2/users").as_slice());
+        assert_eq!("", extension("/.htaccess").as_slice());
+        assert_eq!("", extension("/file.a-b").as_slice());
+        assert_eq!("", extension("/").as_slice());
+    }
+
+    #[test]
+    fn test_category() {
+        assert!(Category::new("/") == Category::Page);
+        assert!(Category::new("/about.html") == Category::Page);
+        assert!(Category::new("/api/users/1.png") == Category::Api);
+        assert!(Category::new("/feed.xml") == Category::Api);
+        assert!(Category::new("/img/logo.PNG") == Category::Image);
+        assert!(Category::new("/fonts/a.woff2") == Category::Font);
+        assert!(Category::new("/video/intro.mp4") == Category::Media);
+        assert!(Category::new("/app.css") == Category::Static);
+        assert!(Category::new("/report.pdf") == Category::Download);
+        assert!(Category::new("/data.bin") == Category::Other);
+    }
+}
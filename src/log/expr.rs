@@ -0,0 +1,409 @@
+use std::ascii::AsciiExt;
+use std::io::net::ip::IpAddr;
+
+use regex::Regex;
+
+use cidr::Cidr;
+
+use super::{HTTPLogRecord, LogFilter, FilterResult};
+use super::field::{Field, FieldValue};
+
+
+// Operators and parentheses with their canonical forms, longer symbols go
+// first. SQL style =, <> and AND, OR, NOT are supported too.
+static SYMBOLS: [(&'static str, &'static str), ..16] = [
+    ("&&", "&&"), ("||", "||"), ("==", "=="), ("!=", "!="), ("<>", "!="),
+    ("!~", "!~"), ("^=", "^="), ("<=", "<="), (">=", ">="), ("=", "=="),
+    ("(", "("), (")", ")"), ("<", "<"), (">", ">"), ("~", "~"), ("!", "!")];
+static KEYWORDS: [(&'static str, &'static str), ..3] =
+    [("and", "&&"), ("or", "||"), ("not", "!")];
+// Characters which end bare words
+static WORD_DELIMITERS: &'static str = "()\"'&|=!<>~^";
+
+#[deriving(Clone, PartialEq, Show)]
+enum Token {
+    Symbol(&'static str),
+    Word(String),
+    Quoted(String),
+}
+
+pub enum Condition {
+    Equal(String),
+    NotEqual(String),
+    Match(Regex),
+    NotMatch(Regex),
+    Prefix(String),
+    InNetwork(Cidr),
+    Less(u64),
+    LessOrEqual(u64),
+    Greater(u64),
+    GreaterOrEqual(u64),
+    NumEqual(u64),
+    NumNotEqual(u64),
+}
+
+// Boolean expression over log record fields like
+// status >= 500 && path ~ "^/api/"
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Field, Condition),
+}
+
+impl Expr {
+    pub fn parse(expr: &str) -> Result<Expr, String> {
+        let tokens = try!(tokenize(expr));
+        let mut parser = Parser{tokens: tokens, pos: 0};
+        let result = try!(parser.parse_or());
+        match parser.next() {
+            None => Ok(result),
+            Some(token) => Err(format!("Unexpected {}", describe(&token)))
+        }
+    }
+
+    pub fn matches(&self, record: &HTTPLogRecord) -> bool {
+        match *self {
+            Expr::And(ref left, ref right) =>
+                left.matches(record) && right.matches(record),
+            Expr::Or(ref left, ref right) =>
+                left.matches(record) || right.matches(record),
+            Expr::Not(ref expr) => !expr.matches(record),
+            Expr::Compare(field, ref condition) =>
+                condition.matches(field.value(record))
+        }
+    }
+}
+
+impl LogFilter for Expr {
+    #[inline]
+    fn filter(&mut self, record: &HTTPLogRecord) -> FilterResult {
+        if self.matches(record) {
+            FilterResult::Accept
+        } else {
+            FilterResult::Skip
+        }
+    }
+}
+
+impl Condition {
+    #[inline]
+    fn matches(&self, value: FieldValue) -> bool {
+        match value {
+            FieldValue::Str(v) => self.matches_str(v),
+            FieldValue::Text(ref v) => self.matches_str(v.as_slice()),
+            FieldValue::Num(v) => self.matches_number(v)
+        }
+    }
+
+    #[inline]
+    fn matches_str(&self, v: &str) -> bool {
+        match *self {
+            Condition::Equal(ref s) => v == s.as_slice(),
+            Condition::NotEqual(ref s) => v != s.as_slice(),
+            Condition::Match(ref re) => re.is_match(v),
+            Condition::NotMatch(ref re) => !re.is_match(v),
+            Condition::Prefix(ref s) => v.starts_with(s.as_slice()),
+            Condition::InNetwork(ref cidr) => match v.parse::<IpAddr>() {
+                Some(addr) => cidr.contains(&addr),
+                None => false
+            },
+            // Types are checked on parsing
+            _ => false
+        }
+    }
+
+    #[inline]
+    fn matches_number(&self, v: u64) -> bool {
+        match *self {
+            Condition::Less(n) => v < n,
+            Condition::LessOrEqual(n) => v <= n,
+            Condition::Greater(n) => v > n,
+            Condition::GreaterOrEqual(n) => v >= n,
+            Condition::NumEqual(n) => v == n,
+            Condition::NumNotEqual(n) => v != n,
+            _ => false
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: uint,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        if self.pos < self.tokens.len() {
+            self.pos += 1;
+            Some(self.tokens[self.pos - 1].clone())
+        } else {
+            None
+        }
+    }
+
+    // Skip the next token if it's the symbol
+    fn skip_symbol(&mut self, symbol: &str) -> bool {
+        if self.pos < self.tokens.len() {
+            match self.tokens[self.pos] {
+                Token::Symbol(s) if s == symbol => {
+                    self.pos += 1;
+                    return true;
+                },
+                _ => {}
+            }
+        }
+        false
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut expr = try!(self.parse_and());
+        while self.skip_symbol("||") {
+            let right = try!(self.parse_and());
+            expr = Expr::Or(box expr, box right);
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut expr = try!(self.parse_unary());
+        while self.skip_symbol("&&") {
+            let right = try!(self.parse_unary());
+            expr = Expr::And(box expr, box right);
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        if self.skip_symbol("!") {
+            let expr = try!(self.parse_unary());
+            Ok(Expr::Not(box expr))
+        } else if self.skip_symbol("(") {
+            let expr = try!(self.parse_or());
+            if self.skip_symbol(")") {
+                Ok(expr)
+            } else {
+                Err("Expected )".to_string())
+            }
+        } else {
+            self.parse_comparison()
+        }
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, String> {
+        let field = match self.next() {
+            Some(Token::Word(name)) => match Field::from_name(name.as_slice()) {
+                Some(field) => field,
+                None => return Err(format!("Unknown field: {}", name))
+            },
+            Some(token) =>
+                return Err(format!("Expected field name, found {}",
+                                   describe(&token))),
+            None => return Err("Expected field name".to_string())
+        };
+        let op = match self.next() {
+            Some(Token::Symbol(op)) => op.to_string(),
+            Some(Token::Word(ref op)) if op.as_slice() == "in" => op.clone(),
+            _ => return Err(format!("Expected operator after {}",
+                                    field.name()))
+        };
+        let value = match self.next() {
+            Some(Token::Word(value)) | Some(Token::Quoted(value)) => value,
+            _ => return Err(format!("Expected value after {} {}",
+                                    field.name(), op))
+        };
+        let condition = if field.is_numeric() {
+            try!(numeric_condition(field, op.as_slice(), value.as_slice()))
+        } else {
+            try!(string_condition(field, op.as_slice(), value))
+        };
+        Ok(Expr::Compare(field, condition))
+    }
+}
+
+fn numeric_condition(field: Field, op: &str, value: &str)
+        -> Result<Condition, String> {
+    let number = match value.parse::<f64>() {
+        Some(number) if number >= 0.0 => number,
+        _ => return Err(format!("Invalid number for {}: {}",
+                                field.name(), value))
+    };
+    // Request time is given in seconds like in the log
+    let n = match field {
+        Field::RequestTime => (number * 1000.0) as u64,
+        _ => number as u64
+    };
+    match op {
+        "==" => Ok(Condition::NumEqual(n)),
+        "!=" => Ok(Condition::NumNotEqual(n)),
+        "<" => Ok(Condition::Less(n)),
+        "<=" => Ok(Condition::LessOrEqual(n)),
+        ">" => Ok(Condition::Greater(n)),
+        ">=" => Ok(Condition::GreaterOrEqual(n)),
+        _ => Err(format!("Operator {} is not supported for numeric field {}",
+                         op, field.name()))
+    }
+}
+
+fn string_condition(field: Field, op: &str, value: String)
+        -> Result<Condition, String> {
+    match op {
+        "==" => Ok(Condition::Equal(value)),
+        "!=" => Ok(Condition::NotEqual(value)),
+        "^=" => Ok(Condition::Prefix(value)),
+        "~" | "!~" => match Regex::new(value.as_slice()) {
+            Ok(re) if op == "~" => Ok(Condition::Match(re)),
+            Ok(re) => Ok(Condition::NotMatch(re)),
+            Err(err) => Err(format!("Invalid regular expression {}: {}",
+                                    value, err))
+        },
+        "in" => match Cidr::parse(value.as_slice()) {
+            Some(cidr) => Ok(Condition::InNetwork(cidr)),
+            None => Err(format!("Invalid network: {}", value))
+        },
+        _ => Err(format!("Operator {} is not supported for string field {}",
+                         op, field.name()))
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match *token {
+        Token::Symbol(symbol) => symbol.to_string(),
+        Token::Word(ref word) => word.clone(),
+        Token::Quoted(ref string) => format!("\"{}\"", string)
+    }
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut tail = expr.trim_left();
+    while !tail.is_empty() {
+        match SYMBOLS.iter().find(|&&(symbol, _)| tail.starts_with(symbol)) {
+            Some(&(symbol, canonical)) => {
+                tokens.push(Token::Symbol(canonical));
+                tail = tail.slice_from(symbol.len());
+            },
+            None if tail.starts_with("\"") || tail.starts_with("'") => {
+                let quote = tail.char_at(0);
+                let (string, rest) = try!(get_quoted(tail.slice_from(1),
+                                                     quote));
+                tokens.push(Token::Quoted(string));
+                tail = rest;
+            },
+            None => {
+                let end = tail.find(|c: char| c.is_whitespace()
+                                    || WORD_DELIMITERS.contains_char(c))
+                    .unwrap_or(tail.len());
+                if end == 0 {
+                    return Err(format!("Unexpected character in: {}", tail));
+                }
+                let word = tail.slice_to(end);
+                let keyword = KEYWORDS.iter()
+                    .find(|&&(keyword, _)| word.eq_ignore_ascii_case(keyword));
+                match keyword {
+                    Some(&(_, symbol)) => tokens.push(Token::Symbol(symbol)),
+                    None => tokens.push(Token::Word(word.to_string()))
+                }
+                tail = tail.slice_from(end);
+            }
+        }
+        tail = tail.trim_left();
+    }
+    Ok(tokens)
+}
+
+// Quoted string with escaped quotes and backslashes, the opening quote is
+// skipped
+fn get_quoted(line: &str, quote: char) -> Result<(String, &str), String> {
+    let mut string = String::new();
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if escaped {
+            string.push(c);
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return Ok((string, line.slice_from(i + 1)));
+        } else {
+            string.push(c);
+        }
+    }
+    Err(format!("Unterminated string: {}{}", quote, line))
+}
+
+/*
+ * Tests
+ */
+#[cfg(test)]
+mod test {
+    use log::{HTTPLogRecord, HTTPStatus, test_record};
+    use super::Expr;
+
+    fn record<'r>(remote_addr: &'r str, path: &'r str, status: u16)
+            -> HTTPLogRecord<'r> {
+        HTTPLogRecord{
+            remote_addr: remote_addr,
+            path: path,
+            status: HTTPStatus::new(status),
+            ..test_record()
+            }
+    }
+
+    #[inline]
+    fn matches(expr: &str, record: &HTTPLogRecord) -> bool {
+        Expr::parse(expr).unwrap().matches(record)
+    }
+
+    #[inline]
+    fn assert_error(exp: &str, expr: &str) {
+        match Expr::parse(expr) {
+            Ok(_) => panic!("expected error for: {}", expr),
+            Err(err) => assert_eq!(exp, err.as_slice())
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let r = record("10.1.2.3", "/api/v1/users", 502);
+        assert!(matches("status >= 500", &r));
+        assert!(matches("status >= 500 && path ~ \"^/api/\"", &r));
+        assert!(matches("path ^= /api/ && host == \"example.com\"", &r));
+        assert!(matches("remote_addr in 10.0.0.0/8", &r));
+        assert!(matches("request_time > 1 && request_time <= 1.5", &r));
+        assert!(matches("!(status < 500) || user != -", &r));
+        assert!(matches("status == 404 || sent_bytes == 1024", &r));
+        assert!(!matches("status < 500 || path !~ api", &r));
+        assert!(!matches("remote_addr in 192.168.0.0/16", &r));
+        assert!(!matches("user_agent == \"Mozilla/5.0 \\\"x\\\"\"", &r));
+    }
+
+    #[test]
+    fn test_sql_style() {
+        let r = record("10.1.2.3", "/api/v1/users", 502);
+        assert!(matches("status = 502 AND NOT (path <> '/api/v1/users')", &r));
+        assert!(matches("status = 200 or method = 'GET'", &r));
+        assert!(matches("status <> 200 and user_agent ~ 'Mozilla'", &r));
+        assert!(matches("not status = 200 && path ^= '/api/'", &r));
+        // Quoted keywords are strings
+        assert!(!matches("method = 'or'", &r));
+        assert_error("Unterminated string: '/api", "path = '/api");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_error("Unknown field: size", "size > 10");
+        assert_error("Expected operator after path", "path");
+        assert_error("Expected value after status >=", "status >=");
+        assert_error("Invalid number for status: abc", "status == abc");
+        assert_error("Operator ~ is not supported for numeric field status",
+                     "status ~ 5");
+        assert_error("Operator < is not supported for string field path",
+                     "path < 5");
+        assert_error("Invalid network: 10.0.0.0/40",
+                     "remote_addr in 10.0.0.0/40");
+        assert_error("Expected )", "(status == 200");
+        assert_error("Unexpected )", "status == 200)");
+        assert_error("Unterminated string: \"/api", "path == \"/api");
+    }
+}
@@ -0,0 +1,131 @@
+use std::cmp;
+
+use super::HTTPLogRecord;
+use super::content::{Category, extension};
+use url::full_url;
+
+
+// Log record fields accessible by name
+#[deriving(Clone, Copy, PartialEq, Show)]
+pub enum Field {
+    RemoteAddr,
+    Host,
+    User,
+    RequestTime,
+    Method,
+    Path,
+    Query,
+    Status,
+    SentBytes,
+    Referer,
+    UserAgent,
+    Scheme,
+    // Derived fields
+    StatusClass,
+    Hour,
+    Date,
+    PathPrefix,
+    Extension,
+    Category,
+    Url,
+}
+
+pub enum FieldValue<'r> {
+    Str(&'r str),
+    Text(String),
+    Num(u64),
+}
+
+impl<'r> FieldValue<'r> {
+    pub fn to_string(&self) -> String {
+        match *self {
+            FieldValue::Str(s) => s.to_string(),
+            FieldValue::Text(ref s) => s.clone(),
+            FieldValue::Num(n) => n.to_string()
+        }
+    }
+}
+
+static FIELDS: [(&'static str, Field), ..19] = [
+    ("remote_addr", Field::RemoteAddr),
+    ("host", Field::Host),
+    ("user", Field::User),
+    ("request_time", Field::RequestTime),
+    ("method", Field::Method),
+    ("path", Field::Path),
+    ("query", Field::Query),
+    ("status", Field::Status),
+    ("sent_bytes", Field::SentBytes),
+    ("referer", Field::Referer),
+    ("user_agent", Field::UserAgent),
+    ("scheme", Field::Scheme),
+    ("status_class", Field::StatusClass),
+    ("hour", Field::Hour),
+    ("date", Field::Date),
+    ("path_prefix", Field::PathPrefix),
+    ("extension", Field::Extension),
+    ("category", Field::Category),
+    ("url", Field::Url),
+    ];
+
+impl Field {
+    pub fn from_name(name: &str) -> Option<Field> {
+        FIELDS.iter().find(|&&(field_name, _)| field_name == name)
+            .map(|&(_, field)| field)
+    }
+
+    pub fn name(&self) -> &'static str {
+        FIELDS.iter().find(|&&(_, field)| field == *self)
+            .map(|&(name, _)| name).unwrap()
+    }
+
+    #[inline]
+    pub fn is_numeric(&self) -> bool {
+        match *self {
+            Field::RequestTime | Field::Status | Field::SentBytes
+                | Field::Hour => true,
+            _ => false
+        }
+    }
+
+    // Field value, request time is in milliseconds
+    #[inline]
+    pub fn value<'r>(&self, record: &HTTPLogRecord<'r>) -> FieldValue<'r> {
+        match *self {
+            Field::RemoteAddr => FieldValue::Str(record.remote_addr),
+            Field::Host => FieldValue::Str(record.host),
+            Field::User => FieldValue::Str(record.user),
+            Field::RequestTime => FieldValue::Num(record.request_time),
+            Field::Method => FieldValue::Str(record.method),
+            Field::Path => FieldValue::Str(record.path),
+            Field::Query => FieldValue::Str(record.query),
+            Field::Status => FieldValue::Num(record.status.code() as u64),
+            Field::SentBytes => FieldValue::Num(record.sent_bytes),
+            Field::Referer => FieldValue::Str(record.referer),
+            Field::UserAgent => FieldValue::Str(record.user_agent),
+            Field::Scheme => FieldValue::Str(record.scheme),
+            Field::StatusClass =>
+                FieldValue::Text(format!("{}xx", record.status.class())),
+            Field::Hour => FieldValue::Num(record.local_time.tm_hour as u64),
+            Field::Date => FieldValue::Text(
+                record.local_time.strftime("%Y-%m-%d").unwrap().to_string()),
+            Field::PathPrefix => FieldValue::Str(path_prefix(record.path)),
+            Field::Extension => FieldValue::Text(extension(record.path)),
+            Field::Category =>
+                FieldValue::Str(Category::new(record.path).name()),
+            Field::Url => FieldValue::Text(full_url(record.scheme, record.host,
+                                                    record.server_port,
+                                                    record.path))
+        }
+    }
+}
+
+// First path segment like /api for /api/v1/users
+#[inline]
+fn path_prefix(path: &str) -> &str {
+    match path.slice_from(cmp::min(1, path.len()))
+            .find(|c: char| c == '/' || c == '?') {
+        Some(pos) => path.slice_to(pos + 1),
+        None => path
+    }
+}
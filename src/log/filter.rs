@@ -0,0 +1,73 @@
+use time::Timespec;
+
+use timezone::timespec;
+
+use super::{HTTPLogRecord, LogFilter, FilterResult};
+
+
+// Accepts records in the time range, inclusive on both ends
+pub struct TimeFilter {
+    since: Option<Timespec>,
+    until: Option<Timespec>,
+    // Input is sorted by time so reading stops after the range end
+    sorted: bool,
+}
+
+impl TimeFilter {
+    pub fn new(since: Option<Timespec>, until: Option<Timespec>,
+               sorted: bool) -> TimeFilter {
+        TimeFilter{since: since, until: until, sorted: sorted}
+    }
+}
+
+impl LogFilter for TimeFilter {
+    #[inline]
+    fn filter(&mut self, record: &HTTPLogRecord) -> FilterResult {
+        if self.since.is_none() && self.until.is_none() {
+            return FilterResult::Accept;
+        }
+        let time = timespec(&record.local_time);
+        match self.since {
+            Some(since) if time < since => return FilterResult::Skip,
+            _ => {}
+        }
+        match self.until {
+            Some(until) if time > until => {
+                if self.sorted {
+                    FilterResult::Stop
+                } else {
+                    FilterResult::Skip
+                }
+            },
+            _ => FilterResult::Accept
+        }
+    }
+}
+
+// All filters should accept a record
+pub struct Filters {
+    filters: Vec<Box<LogFilter + 'static>>,
+}
+
+impl Filters {
+    pub fn new() -> Filters {
+        Filters{filters: Vec::new()}
+    }
+
+    pub fn add(&mut self, filter: Box<LogFilter + 'static>) {
+        self.filters.push(filter);
+    }
+}
+
+impl LogFilter for Filters {
+    #[inline]
+    fn filter(&mut self, record: &HTTPLogRecord) -> FilterResult {
+        for filter in self.filters.iter_mut() {
+            match filter.filter(record) {
+                FilterResult::Accept => {},
+                result => return result
+            }
+        }
+        FilterResult::Accept
+    }
+}
@@ -2,18 +2,51 @@ use std::fmt::{Show, Formatter, Result};
 use std::hash::Hash;
 
 use time::Tm;
+#[cfg(test)]
+use time::empty_tm;
 
 use self::utils::http_status_description;
 
+pub mod bots;
+pub mod content;
+pub mod expr;
+pub mod field;
+pub mod filter;
 pub mod nginx;
+pub mod proxy;
 mod utils;
 
 
-#[deriving(PartialEq, Eq, Hash, Copy)]
+#[deriving(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Copy)]
 pub struct HTTPStatus {
     status: u16,
 }
 
+impl HTTPStatus {
+    pub fn new(status: u16) -> HTTPStatus {
+        HTTPStatus{status: status}
+    }
+
+    #[inline]
+    pub fn code(&self) -> u16 {
+        self.status
+    }
+
+    // Status class: 1 for 1xx, 2 for 2xx etc. and 0 for unknown statuses
+    #[inline]
+    pub fn class(&self) -> uint {
+        match self.status / 100 {
+            class @ 1...5 => class as uint,
+            _ => 0
+        }
+    }
+
+    #[inline]
+    pub fn is_error(&self) -> bool {
+        self.status >= 400 && self.status < 600
+    }
+}
+
 impl Show for HTTPStatus {
     fn fmt(&self, f: &mut Formatter) -> Result {
         let desc = http_status_description(self.status);
@@ -23,6 +56,7 @@ impl Show for HTTPStatus {
 }
 
 // HTTP log record
+#[deriving(Clone)]
 pub struct HTTPLogRecord<'r> {
     pub remote_addr: &'r str,
     pub local_time: Tm,
@@ -32,12 +66,58 @@ pub struct HTTPLogRecord<'r> {
     pub request_time: u64,
     pub method: &'r str,
     pub path: &'r str,
+    // Query string without the leading ?
+    pub query: &'r str,
     pub status: HTTPStatus,
     pub sent_bytes: u64,
     pub referer: &'r str,
     pub user_agent: &'r str,
+    // Scheme and server port if they are in the log format, empty otherwise
+    pub scheme: &'r str,
+    pub server_port: &'r str,
+    // Proxy headers if they are in the log format, empty otherwise
+    pub forwarded_for: &'r str,
+    pub realip_remote_addr: &'r str,
+    // Bot name if the client is detected as a bot
+    pub bot: Option<&'r str>,
+}
+
+// Record with typical values for tests, fields are overridden with the
+// struct update syntax
+#[cfg(test)]
+pub fn test_record() -> HTTPLogRecord<'static> {
+    HTTPLogRecord{
+        remote_addr: "127.0.0.1",
+        local_time: empty_tm(),
+        host: "example.com",
+        user: "-",
+        request_time: 1500,
+        method: "GET",
+        path: "/",
+        query: "",
+        status: HTTPStatus::new(200),
+        sent_bytes: 1024,
+        referer: "-",
+        user_agent: "Mozilla/5.0",
+        scheme: "",
+        server_port: "",
+        forwarded_for: "",
+        realip_remote_addr: "",
+        bot: None,
+        }
 }
 
 pub trait LogProcessor {
     fn process(&mut self, record: HTTPLogRecord);
 }
+
+pub enum FilterResult {
+    Accept,
+    Skip,
+    // Skip the record and stop parsing
+    Stop,
+}
+
+pub trait LogFilter {
+    fn filter(&mut self, record: &HTTPLogRecord) -> FilterResult;
+}
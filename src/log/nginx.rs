@@ -1,129 +1,479 @@
-use time::{Tm, strptime};
+use time::{Tm, strptime, empty_tm};
 
-use super::{HTTPLogRecord, LogProcessor, HTTPStatus};
+use timezone::TimeZone;
 
+use super::{HTTPLogRecord, LogProcessor, HTTPStatus, LogFilter, FilterResult};
+use super::bots::BotDetector;
+use super::proxy::ClientResolver;
+
+
+// Format of the log by default
+pub static DEFAULT_FORMAT: &'static str =
+    "$remote_addr $remote_user [$time_local] $host $pipe $request_time \
+     \"$request\" $status $body_bytes_sent \"$http_referer\" \
+     \"$http_user_agent\"";
+
+// Supported variables of the nginx log_format directive, values of other
+// variables are skipped
+#[deriving(Clone, Copy, PartialEq, Show)]
+enum Variable {
+    RemoteAddr,
+    RemoteUser,
+    TimeLocal,
+    TimeIso8601,
+    Host,
+    RequestTime,
+    Request,
+    RequestMethod,
+    RequestUri,
+    Status,
+    SentBytes,
+    Referer,
+    UserAgent,
+    Scheme,
+    ServerPort,
+    ForwardedFor,
+    RealipRemoteAddr,
+    Other,
+}
+
+static VARIABLES: [(&'static str, Variable), ..20] = [
+    ("remote_addr", Variable::RemoteAddr),
+    ("remote_user", Variable::RemoteUser),
+    ("time_local", Variable::TimeLocal),
+    ("time_iso8601", Variable::TimeIso8601),
+    ("host", Variable::Host),
+    ("http_host", Variable::Host),
+    ("server_name", Variable::Host),
+    ("request_time", Variable::RequestTime),
+    ("request", Variable::Request),
+    ("request_method", Variable::RequestMethod),
+    ("request_uri", Variable::RequestUri),
+    ("status", Variable::Status),
+    ("body_bytes_sent", Variable::SentBytes),
+    ("bytes_sent", Variable::SentBytes),
+    ("http_referer", Variable::Referer),
+    ("http_user_agent", Variable::UserAgent),
+    ("scheme", Variable::Scheme),
+    ("server_port", Variable::ServerPort),
+    ("http_x_forwarded_for", Variable::ForwardedFor),
+    ("realip_remote_addr", Variable::RealipRemoteAddr),
+    ];
+
+#[deriving(Clone)]
+enum Item {
+    Literal(String),
+    Variable(Variable),
+}
+
+// Parsed log_format like '$remote_addr [$time_local] "$request"', each
+// variable value ends at the following literal
+#[deriving(Clone)]
+pub struct LogFormat {
+    items: Vec<Item>,
+}
+
+impl LogFormat {
+    pub fn parse(format: &str) -> Result<LogFormat, String> {
+        let mut items = Vec::new();
+        let mut tail = format;
+        while !tail.is_empty() {
+            let (item, rest) = match tail.find('$') {
+                Some(0) => try!(get_variable(tail)),
+                Some(pos) => (Item::Literal(tail.slice_to(pos).to_string()),
+                              tail.slice_from(pos)),
+                None => (Item::Literal(tail.to_string()), "")
+            };
+            match (items.last(), &item) {
+                (Some(&Item::Variable(_)), &Item::Variable(_)) =>
+                    return Err(format!("Variables should be separated: {}",
+                                       tail)),
+                _ => {}
+            }
+            items.push(item);
+            tail = rest;
+        }
+        Ok(LogFormat{items: items})
+    }
+
+    // Check if the format has a supported variable like http_host
+    pub fn has_variable(&self, name: &str) -> bool {
+        let variable = find_variable(name);
+        variable != Variable::Other && self.items.iter().any(|item|
+            match *item {
+                Item::Variable(item_variable) => item_variable == variable,
+                Item::Literal(_) => false
+            })
+    }
+}
+
+// Variable like $status or ${status}
+fn get_variable(format: &str) -> Result<(Item, &str), String> {
+    let (name, tail) = if format.starts_with("${") {
+        match format.find('}') {
+            Some(end) => (format.slice(2, end), format.slice_from(end + 1)),
+            None => return Err(format!("Unterminated variable: {}", format))
+        }
+    } else {
+        let end = format.slice_from(1)
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .map_or(format.len(), |pos| pos + 1);
+        (format.slice(1, end), format.slice_from(end))
+    };
+    if name.is_empty() {
+        return Err(format!("Empty variable name: {}", format));
+    }
+    Ok((Item::Variable(find_variable(name)), tail))
+}
+
+#[inline]
+fn find_variable(name: &str) -> Variable {
+    VARIABLES.iter().find(|&&(var_name, _)| var_name == name)
+        .map_or(Variable::Other, |&(_, variable)| variable)
+}
 
 pub struct NginxLogParser<B> {
-    buffer: B
+    buffer: B,
+    format: LogFormat,
+    resolver: ClientResolver,
+    timezone: TimeZone,
+    detector: BotDetector,
 }
 
 impl<B: Buffer> NginxLogParser<B> {
-    pub fn new(buffer: B) -> NginxLogParser<B> {
-        NginxLogParser{buffer: buffer}
+    pub fn new(buffer: B, format: LogFormat, resolver: ClientResolver,
+               timezone: TimeZone, detector: BotDetector)
+            -> NginxLogParser<B> {
+        NginxLogParser{
+            buffer: buffer,
+            format: format,
+            resolver: resolver,
+            timezone: timezone,
+            detector: detector,
+            }
     }
 
-    pub fn parse<P: LogProcessor>(&mut self, processor: &mut P) {
+    pub fn parse<F: LogFilter, P: LogProcessor>(&mut self, filter: &mut F,
+                                                processor: &mut P) {
         for result in self.buffer.lines() {
             let line = result.unwrap();
-            let record = create_log_record(line.as_slice());
-            processor.process(record);
+            let mut record = create_log_record(line.as_slice(), &self.format);
+            // Client behind trusted proxies is used as the remote address
+            record.remote_addr = self.resolver.resolve(&record);
+            // Filters and processors use time in the selected time zone
+            record.local_time = self.timezone.convert(&record.local_time);
+            // Bots are detected on all records since filters drop requests
+            // that show the behaviour
+            record.bot = self.detector.detect(&record);
+            match filter.filter(&record) {
+                FilterResult::Accept => processor.process(record),
+                FilterResult::Skip => {},
+                FilterResult::Stop => break
+            }
         }
     }
 }
 
 #[inline]
-fn create_log_record(line: &str) -> HTTPLogRecord {
-    let (remote_addr, tail) = get_field(line);
-    let (user, tail) = get_field(tail);
-    let (local_time, tail) = get_local_time(tail);
-    let (host, mut tail) = get_field(tail);
-    // Pipe
-    tail = skip_field(tail);
-    let (request_time, tail) = get_request_time(tail);
-    let ((method, path), tail) = get_method_path(tail);
-    let (status, tail) = get_int(tail);
-    let (sent_bytes, tail) = get_int(tail);
-    let (referer, tail) = get_delimited_field(tail, '"', '"');
-    let (user_agent, _) = get_delimited_field(tail, '"', '"');
-    HTTPLogRecord{
-        remote_addr: remote_addr,
-        local_time: local_time,
-        host: host,
-        user: user,
-        request_time: request_time,
-        method: method,
-        path: path,
-        status: HTTPStatus{status: status as u16},
-        sent_bytes: sent_bytes,
-        referer: referer,
-        user_agent: user_agent,
-        }
+fn create_log_record<'r>(line: &'r str, format: &LogFormat)
+        -> HTTPLogRecord<'r> {
+    let mut record = HTTPLogRecord{
+        remote_addr: "-",
+        local_time: empty_tm(),
+        host: "-",
+        user: "-",
+        request_time: 0,
+        method: "",
+        path: "",
+        query: "",
+        status: HTTPStatus::new(0),
+        sent_bytes: 0,
+        referer: "-",
+        user_agent: "-",
+        scheme: "",
+        server_port: "",
+        forwarded_for: "",
+        realip_remote_addr: "",
+        bot: None,
+        };
+    let mut tail = line;
+    for (i, item) in format.items.iter().enumerate() {
+        let variable = match *item {
+            Item::Literal(ref literal) => {
+                tail = match skip_literal(tail, literal.as_slice()) {
+                    Some(rest) => rest,
+                    None => panic!("incomplete string: {}", line)
+                };
+                continue;
+            },
+            Item::Variable(variable) => variable
+        };
+        let (value, rest) = match format.items.as_slice().get(i + 1) {
+            Some(&Item::Literal(ref literal)) =>
+                match find_literal(tail, literal.as_slice()) {
+                    Some(end) => (tail.slice_to(end), tail.slice_from(end)),
+                    None => panic!("incomplete string: {}", line)
+                },
+            _ => (tail, "")
+        };
+        set_value(&mut record, variable, value, line);
+        tail = rest;
+    }
+    record
 }
 
+// Start of the literal following a value, a value ends at whitespace if the
+// literal starts with it
 #[inline]
-fn get_field(line: &str) -> (&str, &str) {
-    let slice = line.trim_left();
-    match slice.find(' ') {
-        Some(end) => (slice.slice_to(end), slice.slice_from(end + 1)),
-        None => panic!("incomplete string: {}", line)
+fn find_literal(line: &str, literal: &str) -> Option<uint> {
+    match literal.words().next() {
+        Some(word) if !literal.starts_with(word) =>
+            line.find(|c: char| c.is_whitespace()),
+        Some(word) => line.find_str(word),
+        None => line.find(|c: char| c.is_whitespace())
     }
 }
 
+// Skip the literal, any amount of whitespace matches whitespace of the
+// literal like fields separated by several spaces
 #[inline]
-fn get_field_or<'t>(line: &'t str, default: &'t str) -> (&'t str, &'t str) {
-    let slice = line.trim_left();
-    match slice.find(' ') {
-        Some(end) => (slice.slice_to(end), slice.slice_from(end + 1)),
-        None => (default, "")
+fn skip_literal<'t>(line: &'t str, literal: &str) -> Option<&'t str> {
+    let mut tail = line;
+    for word in literal.words() {
+        tail = tail.trim_left();
+        if !tail.starts_with(word) {
+            return None;
+        }
+        tail = tail.slice_from(word.len());
+    }
+    if literal.ends_with(" ") {
+        tail = tail.trim_left();
     }
+    Some(tail)
 }
 
 #[inline]
-fn skip_field(line: &str) -> &str {
-    let slice = line.trim_left();
-    match slice.find(' ') {
-        Some(end) => slice.slice_from(end + 1),
-        None => panic!("incomplete string: {}", line)
+fn set_value<'r>(record: &mut HTTPLogRecord<'r>, variable: Variable,
+        value: &'r str, line: &str) {
+    match variable {
+        Variable::RemoteAddr => record.remote_addr = value,
+        Variable::RemoteUser => record.user = value,
+        Variable::TimeLocal =>
+            record.local_time = get_time(value, "%d/%b/%Y:%H:%M:%S %z", line),
+        Variable::TimeIso8601 =>
+            record.local_time = get_iso_time(value, line),
+        Variable::Host => record.host = value,
+        Variable::RequestTime =>
+            record.request_time = get_request_time(value, line),
+        Variable::Request => {
+            let (method, path, query) = get_method_path(value);
+            record.method = method;
+            record.path = path;
+            record.query = query;
+        },
+        Variable::RequestMethod => record.method = value,
+        Variable::RequestUri => {
+            let (path, query) = split_query(value);
+            record.path = path;
+            record.query = query;
+        },
+        Variable::Status =>
+            record.status = HTTPStatus::new(get_int(value) as u16),
+        Variable::SentBytes => record.sent_bytes = get_int(value),
+        Variable::Referer => record.referer = value,
+        Variable::UserAgent => record.user_agent = value,
+        Variable::Scheme => record.scheme = value,
+        Variable::ServerPort => record.server_port = value,
+        Variable::ForwardedFor => record.forwarded_for = value,
+        Variable::RealipRemoteAddr => record.realip_remote_addr = value,
+        Variable::Other => {}
     }
 }
 
 #[inline]
-fn get_delimited_field(line: &str, start_c: char, end_c: char) ->
-        (&str, &str) {
-    match line.trim_left().slice_shift_char() {
-        Some((c, slice)) if c == start_c =>
-            // FIXME: Should we skip escaped end characters? But probably
-            // not so important in this case
-            match slice.find(end_c) {
-                Some(end) => (slice.slice_to(end), slice.slice_from(end + 1)),
-                None => panic!("incomplete string: {}", line)
-            },
-        _ => panic!("incomplete string: {}", line)
+fn get_time(value: &str, format: &str, line: &str) -> Tm {
+    match strptime(value, format) {
+        Ok(local_time) => local_time,
+        Err(err) => panic!("time parse error for {}: {}", line, err)
     }
 }
 
+// ISO 8601 time, the colon in the offset like +03:00 isn't supported by
+// strptime so the offset is parsed separately
 #[inline]
-fn get_local_time(line: &str) -> (Tm, &str) {
-    let (slice, tail) = get_delimited_field(line, '[', ']');
-    match strptime(slice, "%d/%b/%Y:%H:%M:%S %z") {
-        Ok(local_time) => (local_time, tail),
-        Err(err) => panic!("time parse error for {}: {}", line, err)
+fn get_iso_time(value: &str, line: &str) -> Tm {
+    let len = value.len();
+    if len < 6 || value.char_at(len - 3) != ':' {
+        return get_time(value, "%Y-%m-%dT%H:%M:%S%z", line);
     }
+    let mut local_time = get_time(value.slice_to(len - 6),
+                                  "%Y-%m-%dT%H:%M:%S", line);
+    let hours = value.slice(len - 5, len - 3).parse::<i32>();
+    let minutes = value.slice_from(len - 2).parse::<i32>();
+    local_time.tm_utcoff = match (value.char_at(len - 6), hours, minutes) {
+        ('+', Some(hours), Some(minutes)) => hours * 3600 + minutes * 60,
+        ('-', Some(hours), Some(minutes)) => -(hours * 3600 + minutes * 60),
+        _ => panic!("time parse error for {}: invalid offset", line)
+    };
+    local_time
 }
 
 #[inline]
-fn get_request_time(line: &str) -> (u64, &str) {
-    let (slice, tail) = get_field(line);
-    match slice.find('.') {
+fn get_request_time(value: &str, line: &str) -> u64 {
+    match value.find('.') {
         Some(pos) => {
-            let sec: u64 = slice.slice_to(pos).parse::<u64>().unwrap();
-            let msec: u64 = slice.slice_from(pos + 1).parse::<u64>().unwrap();
-            (sec * 1000 + msec, tail)
+            let sec: u64 = value.slice_to(pos).parse::<u64>().unwrap();
+            let msec: u64 = value.slice_from(pos + 1).parse::<u64>().unwrap();
+            sec * 1000 + msec
         }
         None => panic!("invalid request time: {}", line)
     }
 }
 
 #[inline]
-fn get_method_path(line: &str) -> ((&str, &str), &str) {
-    let (slice, tail) = get_delimited_field(line, '"', '"');
-    let (method, req_tail) = get_field_or(slice, "");
-    let (path, _) = get_field_or(req_tail, "");
-    ((method, path), tail)
+fn get_method_path(request: &str) -> (&str, &str, &str) {
+    let (method, tail) = get_field_or(request, "");
+    let (url, _) = get_field_or(tail, "");
+    let (path, query) = split_query(url);
+    (method, path, query)
+}
+
+#[inline]
+fn split_query(url: &str) -> (&str, &str) {
+    match url.find('?') {
+        Some(pos) => (url.slice_to(pos), url.slice_from(pos + 1)),
+        None => (url, "")
+    }
 }
 
 #[inline]
-fn get_int(line: &str) -> (u64, &str) {
-    let (slice, tail) = get_field(line);
-    (slice.parse::<u64>().unwrap(), tail)
+fn get_field_or<'t>(line: &'t str, default: &'t str) -> (&'t str, &'t str) {
+    let slice = line.trim_left();
+    match slice.find(' ') {
+        Some(end) => (slice.slice_to(end), slice.slice_from(end + 1)),
+        None => (default, "")
+    }
+}
+
+#[inline]
+fn get_int(value: &str) -> u64 {
+    value.parse::<u64>().unwrap()
+}
+
+/*
+ * Tests
+ */
+#[cfg(test)]
+mod test {
+    use std::io::{BufferedReader, MemReader};
+
+    use log::{HTTPLogRecord, LogProcessor};
+    use log::bots::BotDetector;
+    use log::expr::Expr;
+    use log::proxy::ClientResolver;
+    use timezone::TimeZone;
+
+    use super::{LogFormat, NginxLogParser, DEFAULT_FORMAT, create_log_record};
+
+    struct Hours {
+        hours: Vec<i32>,
+    }
+
+    impl LogProcessor for Hours {
+        fn process(&mut self, record: HTTPLogRecord) {
+            self.hours.push(record.local_time.tm_hour);
+        }
+    }
+
+    #[test]
+    fn test_default_format() {
+        let format = LogFormat::parse(DEFAULT_FORMAT).unwrap();
+        let record = create_log_record(
+            "127.0.0.1 - [10/Oct/2014:13:55:36 +0200] example.com . 0.123 \
+             \"GET /a/b?c=d HTTP/1.1\" 200 2326 \"http://example.com/\" \
+             \"Mozilla/5.0 (X11)\"", &format);
+        assert_eq!("127.0.0.1", record.remote_addr);
+        assert_eq!("-", record.user);
+        assert_eq!(13, record.local_time.tm_hour);
+        assert_eq!(7200, record.local_time.tm_utcoff);
+        assert_eq!("example.com", record.host);
+        assert_eq!(123, record.request_time);
+        assert_eq!("GET", record.method);
+        assert_eq!("/a/b", record.path);
+        assert_eq!("c=d", record.query);
+        assert_eq!(200, record.status.code());
+        assert_eq!(2326, record.sent_bytes);
+        assert_eq!("http://example.com/", record.referer);
+        assert_eq!("Mozilla/5.0 (X11)", record.user_agent);
+        assert_eq!("", record.scheme);
+    }
+
+    #[test]
+    fn test_custom_format() {
+        let format = LogFormat::parse(
+            "$remote_addr - $remote_user [$time_iso8601] ${scheme}://$host:\
+             $server_port \"$request_method $request_uri\" $status \
+             $bytes_sent $upstream_addr").unwrap();
+        let record = create_log_record(
+            "::1 - bob [2014-10-10T13:55:36+02:00] https://example.com:8443 \
+             \"POST /api?x=1\" 201 12 10.0.0.2:80", &format);
+        assert_eq!("::1", record.remote_addr);
+        assert_eq!("bob", record.user);
+        assert_eq!(7200, record.local_time.tm_utcoff);
+        assert_eq!("https", record.scheme);
+        assert_eq!("example.com", record.host);
+        assert_eq!("8443", record.server_port);
+        assert_eq!("POST", record.method);
+        assert_eq!("/api", record.path);
+        assert_eq!("x=1", record.query);
+        assert_eq!(201, record.status.code());
+        assert_eq!(12, record.sent_bytes);
+    }
+
+    #[test]
+    fn test_extra_whitespace() {
+        let format = LogFormat::parse(DEFAULT_FORMAT).unwrap();
+        let record = create_log_record(
+            "127.0.0.1  -  [10/Oct/2014:13:55:36 +0200]  example.com .  \
+             0.123 \"GET / HTTP/1.1\"  200 2326 \"-\"  \"curl/7.38\"",
+            &format);
+        assert_eq!("127.0.0.1", record.remote_addr);
+        assert_eq!("example.com", record.host);
+        assert_eq!(123, record.request_time);
+        assert_eq!("/", record.path);
+        assert_eq!(200, record.status.code());
+        assert_eq!("curl/7.38", record.user_agent);
+    }
+
+    #[test]
+    fn test_filter_timezone() {
+        // Filters see time converted to the time zone
+        let log = "127.0.0.1 - [10/Oct/2014:13:55:36 +0200] example.com . \
+                   0.123 \"GET / HTTP/1.1\" 200 2326 \"-\" \"curl/7.38\"\n";
+        let reader = BufferedReader::new(MemReader::new(log.as_bytes()
+                                                        .to_vec()));
+        let mut parser = NginxLogParser::new(
+            reader, LogFormat::parse(DEFAULT_FORMAT).unwrap(),
+            ClientResolver::new(Vec::new()), TimeZone::Utc,
+            BotDetector::new());
+        let mut filter = Expr::parse("hour == 11").unwrap();
+        let mut hours = Hours{hours: Vec::new()};
+        parser.parse(&mut filter, &mut hours);
+        assert_eq!(vec![11], hours.hours);
+    }
+
+    #[test]
+    fn test_has_variable() {
+        let format = LogFormat::parse(DEFAULT_FORMAT).unwrap();
+        assert!(format.has_variable("request_time"));
+        assert!(!format.has_variable("http_x_forwarded_for"));
+        assert!(!format.has_variable("pipe"));
+    }
+
+    #[test]
+    fn test_format_errors() {
+        assert!(LogFormat::parse("$status$body_bytes_sent").is_err());
+        assert!(LogFormat::parse("${status").is_err());
+        assert!(LogFormat::parse("$ $status").is_err());
+    }
 }
@@ -0,0 +1,116 @@
+use std::io::net::ip::IpAddr;
+
+use cidr::Cidr;
+
+use super::HTTPLogRecord;
+
+
+// Real client address behind trusted proxies from X-Forwarded-For
+#[deriving(Clone)]
+pub struct ClientResolver {
+    trusted: Vec<Cidr>,
+}
+
+impl ClientResolver {
+    pub fn new(trusted: Vec<Cidr>) -> ClientResolver {
+        ClientResolver{trusted: trusted}
+    }
+
+    #[inline]
+    pub fn resolve<'r>(&self, record: &HTTPLogRecord<'r>) -> &'r str {
+        if self.trusted.is_empty() {
+            return record.remote_addr;
+        }
+        self.client(record.remote_addr, record.realip_remote_addr,
+                    record.forwarded_for)
+    }
+
+    // Walk the chain of the peer address ($realip_remote_addr if it's set
+    // by the realip module) and X-Forwarded-For addresses right to left,
+    // the first untrusted address is the client. Like the nginx realip
+    // module the walk stops at an invalid address keeping the last valid
+    // one.
+    pub fn client<'a>(&self, remote_addr: &'a str, realip_remote_addr: &'a str,
+                      forwarded_for: &'a str) -> &'a str {
+        let mut client = if realip_remote_addr.is_empty() {
+            remote_addr
+        } else {
+            realip_remote_addr
+        };
+        if !self.is_trusted(client) {
+            return client;
+        }
+        let addrs: Vec<&str> = forwarded_for.split(',')
+            .map(|addr| strip_port(addr.trim()))
+            .filter(|addr| !addr.is_empty() && *addr != "-")
+            .collect();
+        for addr in addrs.iter().rev() {
+            if addr.parse::<IpAddr>().is_none() {
+                break;
+            }
+            client = *addr;
+            if !self.is_trusted(client) {
+                break;
+            }
+        }
+        client
+    }
+
+    #[inline]
+    fn is_trusted(&self, addr: &str) -> bool {
+        match addr.parse::<IpAddr>() {
+            Some(addr) => self.trusted.iter().any(|cidr| cidr.contains(&addr)),
+            None => false
+        }
+    }
+}
+
+// Address without port like 10.0.0.1 for 10.0.0.1:80 or ::1 for [::1]:80
+#[inline]
+fn strip_port(addr: &str) -> &str {
+    if addr.starts_with("[") {
+        match addr.find(']') {
+            Some(end) => addr.slice(1, end),
+            None => addr
+        }
+    } else {
+        match (addr.find(':'), addr.rfind(':')) {
+            (Some(pos), Some(rpos)) if pos == rpos => addr.slice_to(pos),
+            _ => addr
+        }
+    }
+}
+
+/*
+ * Tests
+ */
+#[cfg(test)]
+mod test {
+    use cidr::Cidr;
+    use super::ClientResolver;
+
+    #[test]
+    fn test_client() {
+        let resolver = ClientResolver::new(vec![
+            Cidr::parse("10.0.0.0/8").unwrap(),
+            Cidr::parse("2001:db8::/32").unwrap()]);
+        assert_eq!("1.2.3.4", resolver.client("10.0.0.1", "", "1.2.3.4"));
+        assert_eq!("5.6.7.8",
+                   resolver.client("10.0.0.1", "",
+                                   "1.2.3.4, 5.6.7.8, 10.0.0.2"));
+        assert_eq!("1.2.3.4",
+                   resolver.client("1.2.3.4", "", "5.6.7.8"));
+        assert_eq!("::1",
+                   resolver.client("1.2.3.4", "2001:db8::1", "[::1]:80"));
+        assert_eq!("5.6.7.8",
+                   resolver.client("10.0.0.1", "", "5.6.7.8:1234"));
+        assert_eq!("10.0.0.3",
+                   resolver.client("10.0.0.1", "", "10.0.0.3, -"));
+        assert_eq!("10.0.0.1", resolver.client("10.0.0.1", "", "-"));
+        assert_eq!("10.0.0.1",
+                   resolver.client("10.0.0.1", "", "1.2.3.4, unknown"));
+        assert_eq!("5.6.7.8",
+                   resolver.client("10.0.0.1", "",
+                                   "1.2.3.4, unknown, 5.6.7.8, 10.0.0.2"));
+    }
+}
@@ -2,41 +2,112 @@ extern crate time;
 extern crate libc;
 extern crate getopts;
 extern crate collections;
+extern crate regex;
 
 use std::os;
 use std::io::util::ChainedReader;
 use std::io::BufferedReader;
-use getopts::{getopts, optopt, usage, OptGroup};
+use getopts::{getopts, optopt, optmulti, optflag, optflagopt, usage, OptGroup,
+              Matches};
+use time::{Timespec, strptime, get_time};
 
-use stats::LogStats;
-use stats::printer::LogStatsPrinter;
-use log::nginx::NginxLogParser;
+use stats::{LogStats, StatsOptions};
+use stats::printer::{LogStatsPrinter, PrinterOptions};
+use stats::query::QueryProcessor;
+use stats::routes::Routes;
+use stats::sql::Query;
+use log::{HTTPStatus, LogProcessor};
+use log::nginx::{NginxLogParser, LogFormat, DEFAULT_FORMAT};
+use log::proxy::ClientResolver;
+use log::bots::{BotDetector, Traffic};
+use log::expr::Expr;
+use log::field::Field;
+use log::filter::{Filters, TimeFilter};
+use cidr::Cidr;
 use gzreader::GzipReader;
+use timezone::TimeZone;
+use useragent::Classifier;
 
 mod log;
 mod stats;
+mod cidr;
 mod gzreader;
+mod timezone;
+mod url;
+mod useragent;
 
 
 static DEFAULT_NUMBER_OF_ITEMS_TO_PRINT: uint = 10u;
+// Relative error of unique clients counting in percent
+static DEFAULT_UNIQUE_ERROR: f64 = 2.0;
+// Dimensions which support bounded top keys tracking
+static TOP_DIMENSIONS: [&'static str, ..4] =
+    ["clients", "paths", "referers", "user-agents"];
+// Number of tracked top keys for the dimensions by default
+static DEFAULT_TOP_CAPACITY: uint = 10000u;
 
-fn parse(filenames: &[String], n: uint) {
+// Query parameters to print values for by default
+static DEFAULT_QUERY_PARAMS: [&'static str, ..2] = ["q", "utm_source"];
+
+// IPv4 and IPv6 prefix lengths of client networks by default
+static DEFAULT_NETWORK_PREFIXES: (uint, uint) = (24, 64);
+
+// Number of top hosts to keep nested stats for by default
+static DEFAULT_PER_HOST: uint = 10u;
+
+// Formats for absolute times of the time range options
+static TIME_FORMATS: [&'static str, ..3] =
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%d"];
+
+struct Config {
+    stats: StatsOptions,
+    format: LogFormat,
+    resolver: ClientResolver,
+    filter: Filters,
+    printer: PrinterOptions,
+}
+
+fn parse<P: LogProcessor>(filenames: &[String], config: &mut Config,
+        processor: &mut P) {
     let files = filenames.iter().map(|filename| {
         let path = Path::new(filename.clone());
         GzipReader::open(&path).unwrap()
         });
     let file = ChainedReader::new(files);
     let reader = BufferedReader::new(file);
-    let mut stats = LogStats::new();
-    let mut parser = NginxLogParser::new(reader);
-    parser.parse(&mut stats);
+    let mut parser = NginxLogParser::new(reader, config.format.clone(),
+                                         config.resolver.clone(),
+                                         config.stats.timezone.clone(),
+                                         BotDetector::new());
+    parser.parse(&mut config.filter, processor);
+}
+
+fn report(filenames: &[String], config: &mut Config) {
+    let mut stats = LogStats::new(&config.stats);
+    parse(filenames, config, &mut stats);
     let printer = LogStatsPrinter::new(&stats);
-    printer.print(n);
+    printer.print(&config.printer);
+}
+
+fn query(sql: &str, filenames: &[String], config: &mut Config)
+        -> Result<(), String> {
+    let query = match Query::parse(sql) {
+        Ok(query) => query,
+        Err(err) => return Err(format!("Invalid query: {}", err))
+    };
+    let mut processor = QueryProcessor::new(query, &config.stats);
+    parse(filenames, config, &mut processor);
+    processor.print();
+    Ok(())
 }
 
 fn print_usage(msg: &str, program: &str, opts: &[OptGroup]) {
-    println!("{}\n\nSimple HTTP statistics, version 0.0.2\n\n\
-              Usage: {} LOGFILE...{}", msg, program, usage("", opts));
+    println!("{0}\n\nSimple HTTP statistics, version 0.0.2\n\n\
+              Usage: {1} LOGFILE...\n       \
+              {1} query SQL LOGFILE...\n\n\
+              Query example: \"SELECT path, count(*), p95(request_time) \
+              FROM log WHERE status = 500 GROUP BY path ORDER BY 2 DESC \
+              LIMIT 20\"{2}", msg, program, usage("", opts));
     os::set_exit_status(2);
 }
 
@@ -53,12 +124,442 @@ fn items_to_print(opt: Option<String>) -> Result<uint, String> {
     }
 }
 
+fn statuses_to_print(strs: Vec<String>)
+        -> Result<Option<Vec<HTTPStatus>>, String> {
+    if strs.is_empty() {
+        return Ok(None);
+    }
+    let mut statuses = Vec::with_capacity(strs.len());
+    for str_status in strs.iter() {
+        match str_status.parse::<u16>() {
+            Some(status) if status >= 100 && status < 600 =>
+                statuses.push(HTTPStatus::new(status)),
+            _ => return Err(str_status.clone())
+        }
+    }
+    Ok(Some(statuses))
+}
+
+fn unique_error(opt: Option<String>) -> Result<f64, String> {
+    match opt {
+        Some(str_error) => {
+            match str_error.parse::<f64>() {
+                Some(error) if error > 0.0 && error < 100.0 => Ok(error),
+                _ => Err(str_error)
+            }
+        },
+        None => Ok(DEFAULT_UNIQUE_ERROR)
+    }
+}
+
+// Capacities are given as DIMENSION=NUMBER or as NUMBER for all dimensions,
+// zero capacity is for exact unbounded tracking
+fn top_capacities(strs: Vec<String>) -> Result<[uint, ..4], String> {
+    let mut capacities = [DEFAULT_TOP_CAPACITY, ..4];
+    for str_capacity in strs.iter() {
+        let (name, str_n) = match str_capacity.find('=') {
+            Some(pos) => (Some(str_capacity.slice_to(pos)),
+                          str_capacity.slice_from(pos + 1)),
+            None => (None, str_capacity.as_slice())
+        };
+        let n = match str_n.parse::<uint>() {
+            Some(n) => n,
+            None => return Err(str_capacity.clone())
+        };
+        match name {
+            Some(name) => {
+                match TOP_DIMENSIONS.iter().position(|d| *d == name) {
+                    Some(i) => capacities[i] = n,
+                    None => return Err(str_capacity.clone())
+                }
+            },
+            None => {
+                for capacity in capacities.iter_mut() {
+                    *capacity = n;
+                }
+            }
+        }
+    }
+    Ok(capacities)
+}
+
+// Parse duration like 30s, 5m, 1h or 1d to seconds
+fn parse_duration(duration: &str) -> Option<i64> {
+    let multiplier = match duration.chars().last() {
+        Some('s') => 1,
+        Some('m') => 60,
+        Some('h') => 60 * 60,
+        Some('d') => 24 * 60 * 60,
+        _ => return None
+    };
+    match duration.slice_to(duration.len() - 1).parse::<i64>() {
+        Some(n) if n > 0 => Some(n * multiplier),
+        _ => None
+    }
+}
+
+fn resolution(opt: Option<String>) -> Result<Option<i64>, String> {
+    match opt {
+        Some(str_resolution) => {
+            match parse_duration(str_resolution.as_slice()) {
+                Some(resolution) => Ok(Some(resolution)),
+                None => Err(str_resolution)
+            }
+        },
+        None => Ok(None)
+    }
+}
+
+fn group_by(opt: Option<String>) -> Result<Option<Vec<Field>>, String> {
+    match opt {
+        Some(str_fields) => {
+            let mut fields = Vec::new();
+            for name in str_fields.as_slice().split(',') {
+                match Field::from_name(name.trim()) {
+                    Some(field) => fields.push(field),
+                    None => return Err(name.to_string())
+                }
+            }
+            Ok(Some(fields))
+        },
+        None => Ok(None)
+    }
+}
+
+fn routes(matches: &Matches) -> Result<Option<Routes>, String> {
+    let rules = matches.opt_strs("route");
+    if rules.is_empty() && !matches.opt_present("normalize-paths") {
+        return Ok(None);
+    }
+    let mut routes = Routes::new(matches.opt_present("normalize-paths"));
+    for rule in rules.iter() {
+        try!(routes.add(rule.as_slice()));
+    }
+    Ok(Some(routes))
+}
+
+// Prefix lengths given as IPV4,IPV6 like 24,64
+fn network_prefixes(matches: &Matches)
+        -> Result<Option<(uint, uint)>, String> {
+    if !matches.opt_present("client-networks") {
+        return Ok(None);
+    }
+    let str_prefixes = match matches.opt_str("client-networks") {
+        Some(str_prefixes) => str_prefixes,
+        None => return Ok(Some(DEFAULT_NETWORK_PREFIXES))
+    };
+    let prefixes: Vec<Option<uint>> = str_prefixes.as_slice().split(',')
+        .map(|prefix| prefix.trim().parse::<uint>())
+        .collect();
+    match prefixes.as_slice() {
+        [Some(ipv4), Some(ipv6)] if ipv4 <= 32 && ipv6 <= 128 =>
+            Ok(Some((ipv4, ipv6))),
+        _ => Err(str_prefixes)
+    }
+}
+
+// Named networks given as NAME=CIDR
+fn networks(strs: Vec<String>) -> Result<Vec<(String, Cidr)>, String> {
+    let mut networks = Vec::with_capacity(strs.len());
+    for str_network in strs.iter() {
+        let (name, str_cidr) = match str_network.find('=') {
+            Some(pos) => (str_network.slice_to(pos),
+                          str_network.slice_from(pos + 1)),
+            None => return Err(str_network.clone())
+        };
+        match Cidr::parse(str_cidr) {
+            Some(cidr) if !name.is_empty() =>
+                networks.push((name.to_string(), cidr)),
+            _ => return Err(str_network.clone())
+        }
+    }
+    Ok(networks)
+}
+
+fn stats_options(matches: &Matches) -> Result<StatsOptions, String> {
+    let error = match unique_error(matches.opt_str("u")) {
+        Ok(error) => error,
+        Err(str_error) =>
+            return Err(format!("Invalid unique clients error provided: {}",
+                               str_error))
+    };
+    let capacities = match top_capacities(matches.opt_strs("k")) {
+        Ok(capacities) => capacities,
+        Err(str_capacity) =>
+            return Err(format!("Invalid top capacity provided: {}",
+                               str_capacity))
+    };
+    let resolution = match resolution(matches.opt_str("r")) {
+        Ok(resolution) => resolution,
+        Err(str_resolution) =>
+            return Err(format!("Invalid resolution provided: {}",
+                               str_resolution))
+    };
+    let timezone = match matches.opt_str("z") {
+        Some(name) => match TimeZone::from_name(name.as_slice()) {
+            Some(timezone) => timezone,
+            None => return Err(format!("Invalid time zone provided: {}", name))
+        },
+        None => TimeZone::Original
+    };
+    let group_by = match group_by(matches.opt_str("g")) {
+        Ok(group_by) => group_by,
+        Err(name) => return Err(format!("Unknown field: {}", name))
+    };
+    let routes = match routes(matches) {
+        Ok(routes) => routes,
+        Err(err) => return Err(format!("Invalid route provided: {}", err))
+    };
+    let tree_depth = match matches.opt_str("tree-depth") {
+        Some(str_depth) => match str_depth.parse::<uint>() {
+            Some(depth) if depth > 0 => Some(depth),
+            _ => return Err(format!("Invalid tree depth provided: {}",
+                                    str_depth))
+        },
+        None => None
+    };
+    let query_params = match matches.opt_strs("query-param") {
+        ref params if params.is_empty() =>
+            DEFAULT_QUERY_PARAMS.iter().map(|name| name.to_string()).collect(),
+        params => params
+    };
+    let classifier = match matches.opt_str("ua-rules") {
+        Some(filename) => match Classifier::from_file(&Path::new(filename)) {
+            Ok(classifier) => classifier,
+            Err(err) =>
+                return Err(format!("Invalid user agent rules: {}", err))
+        },
+        None => Classifier::new()
+    };
+    let network_prefixes = match network_prefixes(matches) {
+        Ok(prefixes) => prefixes,
+        Err(str_prefixes) =>
+            return Err(format!("Invalid network prefixes provided: {}",
+                               str_prefixes))
+    };
+    let per_host = match matches.opt_str("per-host") {
+        Some(str_hosts) => match str_hosts.parse::<uint>() {
+            Some(hosts) if hosts > 0 => Some(hosts),
+            _ => return Err(format!("Invalid number of hosts provided: {}",
+                                    str_hosts))
+        },
+        None if matches.opt_present("per-host") => Some(DEFAULT_PER_HOST),
+        None => None
+    };
+    let networks = match networks(matches.opt_strs("network")) {
+        Ok(networks) => networks,
+        Err(str_network) =>
+            return Err(format!("Invalid network provided: {}", str_network))
+    };
+    Ok(StatsOptions{
+        unique_error: error / 100.0,
+        clients_capacity: capacities[0],
+        paths_capacity: capacities[1],
+        referers_capacity: capacities[2],
+        user_agents_capacity: capacities[3],
+        resolution: resolution,
+        timezone: timezone,
+        group_by: group_by,
+        routes: routes,
+        tree_depth: tree_depth,
+        query_params: query_params,
+        registrable_domains: matches.opt_present("registrable-domains"),
+        classifier: classifier,
+        full_urls: matches.opt_present("full-urls"),
+        per_host: per_host,
+        network_prefixes: network_prefixes,
+        networks: networks,
+        })
+}
+
+// Parse absolute time in the time zone or relative time like -20m
+fn parse_time(str_time: &str, timezone: &TimeZone) -> Option<Timespec> {
+    if str_time.starts_with("-") {
+        return parse_duration(str_time.slice_from(1)).map(|duration| {
+            let now = get_time();
+            Timespec::new(now.sec - duration, now.nsec)
+        });
+    }
+    for format in TIME_FORMATS.iter() {
+        match strptime(str_time, *format) {
+            Ok(time) => return Some(timezone.to_timespec(&time)),
+            Err(_) => {}
+        }
+    }
+    None
+}
+
+fn time_filter(matches: &Matches, timezone: &TimeZone)
+        -> Result<TimeFilter, String> {
+    let mut times = Vec::with_capacity(2);
+    for name in ["since", "until"].iter() {
+        let time = match matches.opt_str(*name) {
+            Some(str_time) => match parse_time(str_time.as_slice(), timezone) {
+                Some(time) => Some(time),
+                None => return Err(format!("Invalid {} time provided: {}",
+                                           *name, str_time))
+            },
+            None => None
+        };
+        times.push(time);
+    }
+    Ok(TimeFilter::new(times[0], times[1], matches.opt_present("sorted")))
+}
+
+fn filters(matches: &Matches, timezone: &TimeZone)
+        -> Result<Filters, String> {
+    let mut filters = Filters::new();
+    filters.add(box try!(time_filter(matches, timezone)));
+    match matches.opt_str("f") {
+        Some(str_expr) => match Expr::parse(str_expr.as_slice()) {
+            Ok(expr) => filters.add(box expr),
+            Err(err) => return Err(format!("Invalid filter: {}", err))
+        },
+        None => {}
+    }
+    match matches.opt_str("traffic") {
+        Some(name) => match Traffic::from_name(name.as_slice()) {
+            Some(traffic) => filters.add(box traffic),
+            None => return Err(format!("Invalid traffic provided: {}", name))
+        },
+        None => {}
+    }
+    Ok(filters)
+}
+
+fn printer_options(matches: &Matches) -> Result<PrinterOptions, String> {
+    let limit = match items_to_print(matches.opt_str("n")) {
+        Ok(n) => n,
+        Err(_str_n) =>
+            return Err("Invalid number of items provided".to_string())
+    };
+    let statuses = match statuses_to_print(matches.opt_strs("s")) {
+        Ok(statuses) => statuses,
+        Err(str_status) =>
+            return Err(format!("Invalid status provided: {}", str_status))
+    };
+    Ok(PrinterOptions{limit: limit, statuses: statuses})
+}
+
+fn config(matches: &Matches) -> Result<Config, String> {
+    // Stats options go first since the time zone is used for time parsing
+    let stats = try!(stats_options(matches));
+    let filter = try!(filters(matches, &stats.timezone));
+    let format = match matches.opt_str("log-format") {
+        Some(format) => match LogFormat::parse(format.as_slice()) {
+            Ok(format) => format,
+            Err(err) => return Err(format!("Invalid log format: {}", err))
+        },
+        None => LogFormat::parse(DEFAULT_FORMAT).unwrap()
+    };
+    let mut trusted = Vec::new();
+    for str_cidr in matches.opt_strs("trusted-proxy").iter() {
+        match Cidr::parse(str_cidr.as_slice()) {
+            Some(cidr) => trusted.push(cidr),
+            None => return Err(format!("Invalid trusted proxy provided: {}",
+                                       str_cidr))
+        }
+    }
+    if !trusted.is_empty() && !format.has_variable("http_x_forwarded_for") {
+        return Err("Trusted proxies need $http_x_forwarded_for in the log \
+                    format".to_string());
+    }
+    let printer = try!(printer_options(matches));
+    Ok(Config{
+        stats: stats,
+        format: format,
+        resolver: ClientResolver::new(trusted),
+        filter: filter,
+        printer: printer,
+        })
+}
+
 fn main() {
     // TODO: We can use an application object here with smaller methods
     let args = os::args();
     let program = args[0].clone();
     let opts = [
-        optopt("n", "", "number of items to print", "NUMBER")
+        optopt("n", "", "number of items to print", "NUMBER"),
+        optmulti("s", "status",
+                 "print top paths for the status (all error statuses by \
+                  default)", "STATUS"),
+        optopt("u", "unique-error",
+               "relative error of unique clients counting in percent \
+                (2 by default)", "PERCENT"),
+        optmulti("k", "top-capacity",
+                 "track only the given number of top clients, paths, \
+                  referers or user-agents with estimated counts (10000 by \
+                  default, 0 for exact counts)", "[DIM=]N"),
+        optopt("r", "resolution",
+               "print requests over time with the given resolution like \
+                1m, 5m, 1h or 1d", "DURATION"),
+        optopt("z", "timezone",
+               "convert times to the time zone before filtering: utc, local \
+                or a name like Europe/Berlin (log record offsets by \
+                default)", "ZONE"),
+        optopt("", "since",
+               "process records since the time like \"2014-10-01 12:00\" \
+                in the time zone or -1h relative to now", "TIME"),
+        optopt("", "until",
+               "process records until the time, same formats as for \
+                --since", "TIME"),
+        optflag("", "sorted",
+                "input is sorted by time, stop reading after --until"),
+        optopt("f", "filter",
+               "process only records matching the expression like \
+                'status >= 500 && path ~ \"^/api/\"'. Fields: remote_addr, \
+                host, user, request_time, method, path, query, status, \
+                sent_bytes, referer, user_agent, scheme, status_class, hour, \
+                date, path_prefix, extension, category, url. Operators: ==, \
+                !=, ~, !~, ^= (prefix), <, <=, >, >=, in (network), &&, ||, \
+                ! and SQL style =, <>, and, or, not. Strings can be quoted \
+                with double or single quotes", "EXPR"),
+        optopt("g", "group-by",
+               "print only stats grouped by the comma separated fields, \
+                the same fields as for --filter", "FIELDS"),
+        optflag("", "normalize-paths",
+                "aggregate paths by routes replacing numeric IDs and UUIDs \
+                 with :id and hex hashes with :hash"),
+        optmulti("", "route",
+                 "aggregate paths matching the regex by the template, \
+                  like '^/blog/[^/]+$ = /blog/:slug'", "RULE"),
+        optopt("", "tree-depth",
+               "print stats by directory prefixes of paths up to the depth \
+                with top subdirectories at each level", "DEPTH"),
+        optmulti("", "query-param",
+                 "print top values of the query string parameter (q and \
+                  utm_source by default)", "NAME"),
+        optflag("", "registrable-domains",
+                "group referring domains by registrable domains like \
+                 example.co.uk instead of full host names"),
+        optopt("", "ua-rules",
+               "file with user agent rules like \"browser | Chrome $1 | \
+                Chrome/(\\d+)\" for kinds browser, os and device, the \
+                first matched rule of each kind wins, crawlers are always \
+                classified as bots", "FILE"),
+        optopt("", "traffic",
+               "process only human or bot traffic detected by user agents \
+                and behaviour: all, human or bot (all by default)", "TRAFFIC"),
+        optopt("", "log-format",
+               "nginx log_format of the log, variables like $scheme and \
+                $server_port are used for full URLs", "FORMAT"),
+        optflag("", "full-urls",
+                "print paths as full URLs with scheme and host"),
+        optflagopt("", "per-host",
+                   "print a full report for each of the top NUMBER hosts \
+                    after the report for all hosts (10 by default)",
+                   "NUMBER"),
+        optflagopt("", "client-networks",
+                   "print clients rolled up into networks with the IPv4 and \
+                    IPv6 prefix lengths (24,64 by default)", "IPV4,IPV6"),
+        optmulti("", "network",
+                 "print clients by named networks like office=10.1.0.0/16, \
+                  can be repeated for the same name", "NAME=CIDR"),
+        optmulti("", "trusted-proxy",
+                 "trust X-Forwarded-For from the proxy network and use the \
+                  client address from it, $http_x_forwarded_for and \
+                  optionally $realip_remote_addr should be in --log-format",
+                 "CIDR")
     ];
     let matches = match getopts(args.tail(), &opts) {
         Ok(m) => m,
@@ -67,17 +568,31 @@ fn main() {
             return;
         }
     };
-    if !matches.free.is_empty() {
-        match items_to_print(matches.opt_str("n")) {
-            Ok(n) => parse(matches.free.as_slice(), n),
-            Err(_str_n) => {
-                print_usage("Invalid number of items provided",
-                            program.as_slice(), &opts);
-                return;
+    // A named time zone is set as the process local time zone once here
+    // before any time is parsed or converted
+    match matches.opt_str("z") {
+        Some(name) => match TimeZone::from_name(name.as_slice()) {
+            Some(timezone) => timezone.set_local(),
+            None => {}
+        },
+        None => {}
+    }
+    let result = match matches.free.as_slice() {
+        [] => Err("No log files provided".to_string()),
+        [ref command, ..] if command.as_slice() == "query" => {
+            match matches.free.as_slice() {
+                [_, ref sql, ref filenames..] if !filenames.is_empty() =>
+                    config(&matches).and_then(|mut config|
+                        query(sql.as_slice(), filenames, &mut config)),
+                [_, _] => Err("No log files provided".to_string()),
+                _ => Err("No query provided".to_string())
             }
-        }
-    } else {
-        print_usage("No log files provided", program.as_slice(), &opts);
-        return;
+        },
+        filenames => config(&matches).map(|mut config|
+            report(filenames, &mut config))
+    };
+    match result {
+        Ok(()) => {},
+        Err(msg) => print_usage(msg.as_slice(), program.as_slice(), &opts)
     }
 }
@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+
+use log::HTTPLogRecord;
+
+use super::{ObjectStats, StatsMap, update};
+use super::topk::TopK;
+
+
+pub struct Crawler {
+    pub stats: ObjectStats,
+    pub paths: StatsMap<String>,
+}
+
+// Human and bot traffic with bots by name
+pub struct BotStats {
+    pub traffic: StatsMap<String>,
+    pub total: ObjectStats,
+    pub crawlers: TopK<String, Crawler>,
+}
+
+impl BotStats {
+    pub fn new(capacity: uint) -> BotStats {
+        BotStats{
+            traffic: HashMap::with_capacity(2),
+            total: ObjectStats::new(),
+            crawlers: TopK::new(capacity),
+            }
+    }
+
+    #[inline]
+    pub fn update(&mut self, record: &HTTPLogRecord) {
+        let name = match record.bot {
+            Some(name) => name,
+            None => {
+                update(&mut self.traffic, "human".to_string(), record);
+                return;
+            }
+        };
+        update(&mut self.traffic, "bot".to_string(), record);
+        self.total.update(record);
+        let crawler = self.crawlers.update(name.to_string(), Crawler{
+            stats: ObjectStats::new(),
+            paths: HashMap::new(),
+            });
+        crawler.stats.update(record);
+        update(&mut crawler.paths, record.path.to_string(), record);
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+use log::HTTPLogRecord;
+use log::content::{Category, extension};
+
+use super::{StatsMap, update};
+
+
+// Full (200) and not modified (304) responses
+pub struct CacheStats {
+    pub ok: u64,
+    pub not_modified: u64,
+}
+
+// Requests by file extension and content category
+pub struct ContentStats {
+    pub extensions: StatsMap<String>,
+    pub categories: StatsMap<Category>,
+    pub cache: HashMap<Category, CacheStats>,
+}
+
+impl ContentStats {
+    pub fn new() -> ContentStats {
+        ContentStats{
+            extensions: HashMap::new(),
+            categories: HashMap::new(),
+            cache: HashMap::new(),
+            }
+    }
+
+    #[inline]
+    pub fn update(&mut self, record: &HTTPLogRecord) {
+        let ext = extension(record.path);
+        let ext = if ext.is_empty() { "none".to_string() } else { ext };
+        update(&mut self.extensions, ext, record);
+        let category = Category::new(record.path);
+        update(&mut self.categories, category, record);
+        let cache = match self.cache.entry(category) {
+            Entry::Vacant(entry) => entry.set(CacheStats{
+                ok: 0,
+                not_modified: 0,
+                }),
+            Entry::Occupied(entry) => entry.into_mut()
+        };
+        match record.status.code() {
+            200 => cache.ok += 1,
+            304 => cache.not_modified += 1,
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+use time::{Tm, Timespec, at_utc};
+
+use timezone::timespec;
+
+
+pub type HourCounts = [u64, ..24];
+
+// Requests by weekday and hour and by date and hour
+pub struct Heatmap {
+    // Indexed by tm_wday, 0 is Sunday
+    pub weekdays: [HourCounts, ..7],
+    pub dates: HashMap<String, HourCounts>,
+}
+
+impl Heatmap {
+    pub fn new() -> Heatmap {
+        Heatmap{
+            weekdays: [[0, ..24], ..7],
+            dates: HashMap::with_capacity(2),
+            }
+    }
+
+    #[inline]
+    pub fn update(&mut self, date: &str, time: &Tm) {
+        let hour = time.tm_hour as uint;
+        self.weekdays[weekday(time)][hour] += 1;
+        let hours = match self.dates.entry(date.to_string()) {
+            Entry::Vacant(entry) => entry.set([0, ..24]),
+            Entry::Occupied(entry) => entry.into_mut()
+        };
+        hours[hour] += 1;
+    }
+}
+
+// Weekday of the wall clock time, computed from the absolute time since
+// strptime doesn't set tm_wday
+#[inline]
+fn weekday(time: &Tm) -> uint {
+    let local = timespec(time).sec + time.tm_utcoff as i64;
+    at_utc(Timespec::new(local, 0)).tm_wday as uint
+}
+
+/*
+ * Tests
+ */
+#[cfg(test)]
+mod test {
+    use time::strptime;
+
+    use super::{Heatmap, weekday};
+
+    #[test]
+    fn test_weekday() {
+        // Friday in the record offset but Thursday in UTC
+        let time = strptime("10/Oct/2014:01:30:00 +0200",
+                            "%d/%b/%Y:%H:%M:%S %z").unwrap();
+        assert_eq!(5, weekday(&time));
+        let time = strptime("12/Oct/2014:23:00:00 -0500",
+                            "%d/%b/%Y:%H:%M:%S %z").unwrap();
+        assert_eq!(0, weekday(&time));
+    }
+
+    #[test]
+    fn test_update() {
+        let mut heatmap = Heatmap::new();
+        let time = strptime("13/Oct/2014:09:15:00 +0000",
+                            "%d/%b/%Y:%H:%M:%S %z").unwrap();
+        heatmap.update("2014-10-13", &time);
+        assert_eq!(1, heatmap.weekdays[1][9]);
+        assert_eq!(0, heatmap.weekdays[0][9]);
+        let hours = heatmap.dates.get(&"2014-10-13".to_string()).unwrap();
+        assert_eq!(1, hours[9]);
+    }
+}
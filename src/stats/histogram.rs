@@ -0,0 +1,111 @@
+use std::num::Int;
+
+
+// Values below are counted exactly, larger ones in log-linear buckets
+static LINEAR_LIMIT: u64 = 16;
+// Sub-buckets per power of two, gives precision about 12.5%
+static SUB_BUCKET_BITS: uint = 3;
+
+// Histogram of request times for percentile estimation
+pub struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+}
+
+impl Histogram {
+    pub fn new() -> Histogram {
+        Histogram{counts: Vec::new(), total: 0}
+    }
+
+    #[inline]
+    pub fn insert(&mut self, value: u64) {
+        let index = bucket_index(value);
+        if index >= self.counts.len() {
+            let grow = index + 1 - self.counts.len();
+            self.counts.grow(grow, 0);
+        }
+        self.counts[index] += 1;
+        self.total += 1;
+    }
+
+    // Upper bound of the value below which the given percent of values fall
+    pub fn percentile(&self, percent: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+        let rank = ((percent / 100.0) * self.total as f64).ceil() as u64;
+        let mut seen = 0;
+        for (index, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank && count != 0 {
+                return bucket_upper_bound(index);
+            }
+        }
+        bucket_upper_bound(self.counts.len() - 1)
+    }
+}
+
+#[inline]
+fn bucket_index(value: u64) -> uint {
+    if value < LINEAR_LIMIT {
+        value as uint
+    } else {
+        let exp = 63 - value.leading_zeros();
+        let mantissa = (value >> (exp - SUB_BUCKET_BITS)) as uint
+                       & ((1 << SUB_BUCKET_BITS) - 1);
+        LINEAR_LIMIT as uint + ((exp - 4) << SUB_BUCKET_BITS) + mantissa
+    }
+}
+
+#[inline]
+fn bucket_upper_bound(index: uint) -> u64 {
+    if index < LINEAR_LIMIT as uint {
+        index as u64
+    } else {
+        let offset = index - LINEAR_LIMIT as uint;
+        let exp = (offset >> SUB_BUCKET_BITS) + 4;
+        let mantissa = (offset & ((1 << SUB_BUCKET_BITS) - 1)) as u64;
+        let step = 1u64 << (exp - SUB_BUCKET_BITS);
+        (((1 << SUB_BUCKET_BITS) + mantissa) << (exp - SUB_BUCKET_BITS))
+            + step - 1
+    }
+}
+
+/*
+ * Tests
+ */
+#[cfg(test)]
+mod test {
+    use super::{Histogram, bucket_index, bucket_upper_bound};
+
+    #[test]
+    fn test_buckets() {
+        assert_eq!(0, bucket_index(0));
+        assert_eq!(15, bucket_index(15));
+        assert_eq!(16, bucket_index(16));
+        assert_eq!(16, bucket_index(17));
+        assert_eq!(17, bucket_index(18));
+        assert_eq!(24, bucket_index(32));
+        assert_eq!(15, bucket_upper_bound(15));
+        assert_eq!(17, bucket_upper_bound(16));
+        assert_eq!(35, bucket_upper_bound(24));
+        for &value in [100u64, 1000, 12345, 1 << 40].iter() {
+            let bound = bucket_upper_bound(bucket_index(value));
+            assert!(bound >= value && bound - value <= value / 8,
+                    "value: {}, bound: {}", value, bound);
+        }
+    }
+
+    #[test]
+    fn test_percentile() {
+        let mut histogram = Histogram::new();
+        assert_eq!(0, histogram.percentile(50.0));
+        for value in range(1u64, 101) {
+            histogram.insert(value);
+        }
+        assert_eq!(1, histogram.percentile(1.0));
+        assert_eq!(51, histogram.percentile(50.0));
+        assert_eq!(103, histogram.percentile(99.0));
+        assert_eq!(103, histogram.percentile(100.0));
+    }
+}
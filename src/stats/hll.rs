@@ -0,0 +1,160 @@
+use std::hash::{hash, Hash};
+use std::num::{Float, Int};
+
+
+// HyperLogLog precision limits, number of registers is 2^precision
+static MIN_PRECISION: uint = 4u;
+static MAX_PRECISION: uint = 16u;
+
+enum Registers {
+    // Exact set of hashes for small cardinalities
+    Sparse(Vec<u64>),
+    Dense(Vec<u8>),
+}
+
+// Approximate distinct counter with memory bounded by 2^precision bytes
+pub struct HyperLogLog {
+    precision: uint,
+    registers: Registers,
+}
+
+impl HyperLogLog {
+    // Create counter with the given relative standard error (0.02 for 2%)
+    pub fn new(error: f64) -> HyperLogLog {
+        HyperLogLog{
+            precision: precision_for_error(error),
+            registers: Registers::Sparse(Vec::new()),
+            }
+    }
+
+    #[inline]
+    pub fn insert<T: Hash>(&mut self, value: &T) {
+        let hashed = hash(value);
+        let dense = match self.registers {
+            Registers::Sparse(ref mut hashes) => {
+                if !hashes.contains(&hashed) {
+                    hashes.push(hashed);
+                }
+                // Sparse representation is used until it takes more than
+                // a half of the dense one
+                if hashes.len() * 8 > (1 << self.precision) / 2 {
+                    let mut registers = Vec::from_elem(1 << self.precision,
+                                                       0u8);
+                    for &h in hashes.iter() {
+                        update_register(registers.as_mut_slice(),
+                                        self.precision, h);
+                    }
+                    Some(registers)
+                } else {
+                    None
+                }
+            },
+            Registers::Dense(ref mut registers) => {
+                update_register(registers.as_mut_slice(), self.precision,
+                                hashed);
+                None
+            }
+        };
+        match dense {
+            Some(registers) => self.registers = Registers::Dense(registers),
+            None => {}
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        match self.registers {
+            Registers::Sparse(ref hashes) => hashes.len() as u64,
+            Registers::Dense(ref registers) => estimate(registers.as_slice())
+        }
+    }
+}
+
+// Standard error of HyperLogLog is about 1.04 / sqrt(2^precision)
+pub fn precision_for_error(error: f64) -> uint {
+    let precision = (2.0 * (1.04 / error).log2()).ceil();
+    if precision < MIN_PRECISION as f64 {
+        MIN_PRECISION
+    } else if precision > MAX_PRECISION as f64 {
+        MAX_PRECISION
+    } else {
+        precision as uint
+    }
+}
+
+#[inline]
+fn update_register(registers: &mut [u8], precision: uint, hashed: u64) {
+    let index = (hashed >> (64 - precision)) as uint;
+    // Position of the first set bit in the rest of the hash
+    let rank = match (hashed << precision).leading_zeros() + 1 {
+        rank if rank > 64 - precision => 64 - precision + 1,
+        rank => rank
+    } as u8;
+    if registers[index] < rank {
+        registers[index] = rank;
+    }
+}
+
+fn estimate(registers: &[u8]) -> u64 {
+    let m = registers.len() as f64;
+    let alpha = match registers.len() {
+        16 => 0.673,
+        32 => 0.697,
+        64 => 0.709,
+        _ => 0.7213 / (1.0 + 1.079 / m)
+    };
+    let mut sum = 0.0f64;
+    let mut zeros = 0u;
+    for &register in registers.iter() {
+        sum += 2.0f64.powi(-(register as i32));
+        if register == 0 {
+            zeros += 1;
+        }
+    }
+    let raw = alpha * m * m / sum;
+    // Linear counting for small cardinalities. Large range correction isn't
+    // needed for 64-bit hashes.
+    let result = if raw <= 2.5 * m && zeros != 0 {
+        m * (m / zeros as f64).ln()
+    } else {
+        raw
+    };
+    result.round() as u64
+}
+
+/*
+ * Tests
+ */
+#[cfg(test)]
+mod test {
+    use super::{HyperLogLog, precision_for_error};
+
+    #[test]
+    fn test_precision_for_error() {
+        assert_eq!(4, precision_for_error(0.5));
+        assert_eq!(11, precision_for_error(0.025));
+        assert_eq!(12, precision_for_error(0.02));
+        assert_eq!(14, precision_for_error(0.01));
+        assert_eq!(16, precision_for_error(0.0001));
+    }
+
+    #[test]
+    fn test_sparse_count() {
+        let mut hll = HyperLogLog::new(0.02);
+        for i in range(0u, 100) {
+            hll.insert(&i);
+            hll.insert(&i);
+        }
+        assert_eq!(100, hll.count());
+    }
+
+    #[test]
+    fn test_dense_count() {
+        let mut hll = HyperLogLog::new(0.02);
+        for i in range(0u, 100000) {
+            hll.insert(&i.to_string());
+        }
+        let count = hll.count() as int;
+        // Within 3 standard errors
+        assert!(count > 94000 && count < 106000, "count: {}", count);
+    }
+}
@@ -0,0 +1,62 @@
+use std::ascii::AsciiExt;
+
+use log::HTTPLogRecord;
+use log::content::Category;
+use url::{split_url, registrable_domain};
+
+use super::{ObjectStats, TopMap, update_top};
+use super::referers::referer_domain;
+use super::topk::TopK;
+
+
+pub struct HotlinkDomain {
+    pub stats: ObjectStats,
+    pub assets: TopMap<String>,
+}
+
+// Images, media and fonts requested with referers from external sites
+pub struct Hotlinks {
+    capacity: uint,
+    registrable_domains: bool,
+    pub total: ObjectStats,
+    pub domains: TopK<String, HotlinkDomain>,
+    pub assets: TopMap<String>,
+}
+
+impl Hotlinks {
+    pub fn new(capacity: uint, registrable_domains: bool) -> Hotlinks {
+        Hotlinks{
+            capacity: capacity,
+            registrable_domains: registrable_domains,
+            total: ObjectStats::new(),
+            domains: TopK::new(capacity),
+            assets: TopK::new(capacity),
+            }
+    }
+
+    #[inline]
+    pub fn update(&mut self, record: &HTTPLogRecord) {
+        if !Category::new(record.path).is_embedded() {
+            return;
+        }
+        let host = match split_url(record.referer) {
+            Some((_, host, _)) => host,
+            None => return
+        };
+        // Other subdomains of the site like www and static aren't external
+        if registrable_domain(host)
+                .eq_ignore_ascii_case(registrable_domain(record.host)) {
+            return;
+        }
+        self.total.update(record);
+        let capacity = self.capacity;
+        let domain = self.domains.update_with(
+            referer_domain(host, self.registrable_domains), || HotlinkDomain{
+                stats: ObjectStats::new(),
+                assets: TopK::new(capacity),
+                });
+        domain.stats.update(record);
+        update_top(&mut domain.assets, record.path.to_string(), record);
+        update_top(&mut self.assets, record.path.to_string(), record);
+    }
+}
@@ -0,0 +1,136 @@
+use std::ascii::AsciiExt;
+
+use log::HTTPLogRecord;
+use url::{split_url, query_params};
+
+use super::{ObjectStats, TopMap, update_top};
+use super::topk::TopK;
+
+
+// Search engines by a host label with the query parameters
+static SEARCH_ENGINES: [(&'static str, &'static [&'static str]), ..8] = [
+    ("google", &["q"]),
+    ("bing", &["q"]),
+    ("yahoo", &["p"]),
+    ("yandex", &["text"]),
+    ("duckduckgo", &["q"]),
+    ("baidu", &["wd", "word"]),
+    ("ask", &["q"]),
+    ("ecosia", &["q"]),
+    ];
+// Query parameters of internal site search
+static SITE_SEARCH_PARAMS: [&'static str, ..4] = ["q", "query", "search", "s"];
+
+// Search terms from referers of search engines and internal site search
+pub struct KeywordStats {
+    pub search: TopMap<String>,
+    pub search_total: ObjectStats,
+    pub site: TopMap<String>,
+    pub site_total: ObjectStats,
+}
+
+impl KeywordStats {
+    pub fn new(capacity: uint) -> KeywordStats {
+        KeywordStats{
+            search: TopK::new(capacity),
+            search_total: ObjectStats::new(),
+            site: TopK::new(capacity),
+            site_total: ObjectStats::new(),
+            }
+    }
+
+    #[inline]
+    pub fn update(&mut self, record: &HTTPLogRecord) {
+        if record.referer == "-" {
+            return;
+        }
+        match keywords(record.referer, record.host) {
+            Some((keywords, false)) => {
+                self.search_total.update(record);
+                update_top(&mut self.search, keywords, record);
+            },
+            Some((keywords, true)) => {
+                self.site_total.update(record);
+                update_top(&mut self.site, keywords, record);
+            },
+            None => {}
+        }
+    }
+}
+
+// Keywords from the referer with a flag if they are from the site search
+// on the host itself
+pub fn keywords(referer: &str, host: &str) -> Option<(String, bool)> {
+    let (_, referer_host, path) = match split_url(referer) {
+        Some(parts) => parts,
+        None => return None
+    };
+    let query = match path.find('?') {
+        Some(pos) => path.slice_from(pos + 1),
+        None => return None
+    };
+    let query = match query.find('#') {
+        Some(pos) => query.slice_to(pos),
+        None => query
+    };
+    let referer_host = referer_host.to_ascii_lower();
+    let (names, internal) = if referer_host.as_slice()
+            .eq_ignore_ascii_case(host) {
+        (SITE_SEARCH_PARAMS.as_slice(), true)
+    } else {
+        match SEARCH_ENGINES.iter().find(|&&(name, _)|
+                referer_host.as_slice().split('.').any(|label| label == name)) {
+            Some(&(_, names)) => (names, false),
+            None => return None
+        }
+    };
+    let params = query_params(query);
+    for name in names.iter() {
+        for &(ref param, ref value) in params.iter() {
+            if param.as_slice() == *name {
+                let folded = fold(value.as_slice());
+                if !folded.is_empty() {
+                    return Some((folded, internal));
+                }
+            }
+        }
+    }
+    None
+}
+
+// Lower case words separated by single spaces
+#[inline]
+fn fold(keywords: &str) -> String {
+    let lower: String = keywords.chars().map(|c| c.to_lowercase()).collect();
+    let words: Vec<&str> = lower.as_slice().words().collect();
+    words.connect(" ")
+}
+
+/*
+ * Tests
+ */
+#[cfg(test)]
+mod test {
+    use super::keywords;
+
+    #[test]
+    fn test_keywords() {
+        assert_eq!(Some(("rust lang".to_string(), false)),
+                   keywords("https://www.google.com/search?q=Rust++Lang&x=1",
+                            "example.com"));
+        assert_eq!(Some(("über".to_string(), false)),
+                   keywords("http://search.yahoo.com/search?p=%C3%9Cber",
+                            "example.com"));
+        assert_eq!(Some(("logs".to_string(), false)),
+                   keywords("https://www.baidu.com/s?word=logs",
+                            "example.com"));
+        assert_eq!(Some(("nginx".to_string(), true)),
+                   keywords("http://Example.com/search?query=nginx#top",
+                            "example.com"));
+        assert_eq!(None, keywords("https://www.google.com/", "example.com"));
+        assert_eq!(None, keywords("https://www.google.com/?q=+",
+                                  "example.com"));
+        assert_eq!(None, keywords("http://other.com/?q=x", "example.com"));
+        assert_eq!(None, keywords("-", "example.com"));
+    }
+}
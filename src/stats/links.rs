@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+
+use log::HTTPLogRecord;
+use url::is_same_host;
+
+use super::{ObjectStats, StatsMap, update};
+use super::topk::TopK;
+
+
+pub struct BrokenLink {
+    pub stats: ObjectStats,
+    // Referers from the same host as the request
+    pub internal: StatsMap<String>,
+    pub external: StatsMap<String>,
+}
+
+// Paths not found (404) or gone (410) with referers sending traffic there
+pub struct BrokenLinks {
+    pub links: TopK<String, BrokenLink>,
+    pub total: ObjectStats,
+}
+
+impl BrokenLinks {
+    pub fn new(capacity: uint) -> BrokenLinks {
+        BrokenLinks{
+            links: TopK::new(capacity),
+            total: ObjectStats::new(),
+            }
+    }
+
+    #[inline]
+    pub fn update(&mut self, record: &HTTPLogRecord) {
+        match record.status.code() {
+            404 | 410 => {},
+            _ => return
+        }
+        self.total.update(record);
+        let link = self.links.update(record.path.to_string(), BrokenLink{
+            stats: ObjectStats::new(),
+            internal: HashMap::new(),
+            external: HashMap::new(),
+            });
+        link.stats.update(record);
+        if record.referer == "-" {
+            return;
+        }
+        let referers = if is_same_host(record.referer, record.host) {
+            &mut link.internal
+        } else {
+            &mut link.external
+        };
+        update(referers, record.referer.to_string(), record);
+    }
+}
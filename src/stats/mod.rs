@@ -1,65 +1,256 @@
+use std::cell::RefCell;
 use std::hash::Hash;
 
 use std::collections::HashMap;
 use std::collections::hash_map::Entry;
+use std::rc::Rc;
 
 use time::{Tm, Timespec};
 
+use cidr::Cidr;
 use log::{HTTPLogRecord, LogProcessor, HTTPStatus};
+use log::field::Field;
+use url::full_url;
+use timezone::TimeZone;
+use useragent::Classifier;
+
+use self::bots::BotStats;
+use self::content::ContentStats;
+use self::heatmap::Heatmap;
+use self::hotlinks::Hotlinks;
+use self::keywords::KeywordStats;
+use self::links::BrokenLinks;
+use self::networks::NetworkStats;
+use self::params::ParamStats;
+use self::referers::RefererStats;
+use self::routes::Routes;
+use self::timeseries::TimeSeries;
+use self::topk::TopK;
+use self::tree::PathTree;
+use self::unique::UniqueClients;
 
 pub mod printer;
+pub mod query;
+pub mod routes;
+pub mod sql;
+pub mod topk;
+mod bots;
+mod content;
+mod heatmap;
+mod histogram;
+mod hll;
+mod hotlinks;
+mod keywords;
+mod links;
+mod networks;
+mod params;
+mod referers;
+mod timeseries;
+mod tree;
+mod unique;
 mod utils;
 
 
+// Number of status classes: unknown, 1xx, 2xx, 3xx, 4xx and 5xx
+static STATUS_CLASSES: uint = 6u;
+
+#[deriving(Clone)]
 struct ObjectStats {
     requests: u64,
     request_time: u64,
-    sent_bytes: u64
+    sent_bytes: u64,
+    // Requests by status class, indexed by HTTPStatus::class()
+    status_classes: [u64, ..STATUS_CLASSES],
+}
+
+impl ObjectStats {
+    fn new() -> ObjectStats {
+        ObjectStats{
+            requests: 0,
+            request_time: 0,
+            sent_bytes: 0,
+            status_classes: [0, ..STATUS_CLASSES],
+            }
+    }
+
+    #[inline]
+    fn update(&mut self, record: &HTTPLogRecord) {
+        self.requests += 1;
+        self.request_time += record.request_time;
+        self.sent_bytes += record.sent_bytes;
+        self.status_classes[record.status.class()] += 1;
+    }
+
+    // Subtract stats saturating at zero
+    #[inline]
+    fn subtract(&mut self, other: &ObjectStats) {
+        self.requests = saturating_sub(self.requests, other.requests);
+        self.request_time = saturating_sub(self.request_time,
+                                           other.request_time);
+        self.sent_bytes = saturating_sub(self.sent_bytes, other.sent_bytes);
+        for i in range(0, STATUS_CLASSES) {
+            self.status_classes[i] = saturating_sub(self.status_classes[i],
+                                                    other.status_classes[i]);
+        }
+    }
+
+    // Client (4xx) and server (5xx) errors
+    #[inline]
+    fn errors(&self) -> u64 {
+        self.status_classes[4] + self.status_classes[5]
+    }
 }
 
 type StatsItem<'r, T> = (&'r T, &'r ObjectStats);
 type StatsMap<T> = HashMap<T, ObjectStats>;
+// Stats for high cardinality dimensions, bounded if capacity is set
+type TopMap<T> = TopK<T, ObjectStats>;
+
+#[deriving(Clone)]
+pub struct StatsOptions {
+    // Relative standard error for unique clients counting
+    pub unique_error: f64,
+    // Number of tracked keys for high cardinality dimensions, zero for
+    // exact unbounded tracking
+    pub clients_capacity: uint,
+    pub paths_capacity: uint,
+    pub referers_capacity: uint,
+    pub user_agents_capacity: uint,
+    // Time series bucket size in seconds
+    pub resolution: Option<i64>,
+    // Time zone which record times are converted to by the parser
+    pub timezone: TimeZone,
+    // Collect only stats grouped by the fields
+    pub group_by: Option<Vec<Field>>,
+    // Aggregate paths by route templates
+    pub routes: Option<Routes>,
+    // Depth of the directory prefix tree of paths
+    pub tree_depth: Option<uint>,
+    // Query string parameters to collect values for
+    pub query_params: Vec<String>,
+    // Group external referers by registrable domains like example.co.uk
+    pub registrable_domains: bool,
+    // Classifier of user agents into browsers, operating systems and devices
+    pub classifier: Classifier,
+    // Key paths by full URLs with scheme and host
+    pub full_urls: bool,
+    // Number of top hosts to collect full nested stats for
+    pub per_host: Option<uint>,
+    // IPv4 and IPv6 prefix lengths to roll up clients into networks
+    pub network_prefixes: Option<(uint, uint)>,
+    // Named networks like office, the first matched network is used
+    pub networks: Vec<(String, Cidr)>,
+}
 
 pub struct LogStats {
+    timezone: TimeZone,
     start: Option<Tm>,
     end: Option<Tm>,
     start_sec: Option<Timespec>,
     end_sec: Option<Timespec>,
     total: ObjectStats,
-    clients: StatsMap<String>,
+    clients: TopMap<String>,
     hosts: StatsMap<String>,
     methods: StatsMap<String>,
-    paths: StatsMap<String>,
+    paths: TopMap<String>,
     statuses: StatsMap<HTTPStatus>,
-    referers: StatsMap<String>,
-    user_agents: StatsMap<String>,
-    hours: StatsMap<u8>,
+    referers: TopMap<String>,
+    user_agents: TopMap<String>,
     dates: StatsMap<String>,
     users: StatsMap<String>,
+    status_paths: HashMap<HTTPStatus, TopMap<String>>,
+    paths_capacity: uint,
+    broken_links: BrokenLinks,
+    unique_clients: UniqueClients,
+    time_series: Option<TimeSeries>,
+    heatmap: Heatmap,
+    group_by: Vec<Field>,
+    groups: Option<TopMap<String>>,
+    routes: Option<Routes>,
+    path_tree: Option<PathTree>,
+    content: ContentStats,
+    params: ParamStats,
+    keywords: KeywordStats,
+    referer_domains: RefererStats,
+    hotlinks: Hotlinks,
+    // Shared with nested stats of hosts to keep a single cache
+    classifier: Rc<RefCell<Classifier>>,
+    browsers: StatsMap<String>,
+    operating_systems: StatsMap<String>,
+    devices: StatsMap<String>,
+    bots: BotStats,
+    full_urls: bool,
+    // Options for nested stats of hosts in the per host mode
+    host_options: Option<Box<StatsOptions>>,
+    host_stats: TopK<String, Box<LogStats>>,
+    networks: Option<NetworkStats>,
 }
 
 impl LogStats {
-    pub fn new() -> LogStats {
+    pub fn new(options: &StatsOptions) -> LogStats {
+        let classifier = Rc::new(RefCell::new(options.classifier.clone()));
+        LogStats::with_classifier(options, classifier)
+    }
+
+    fn with_classifier(options: &StatsOptions,
+                       classifier: Rc<RefCell<Classifier>>) -> LogStats {
         LogStats{
+            timezone: options.timezone.clone(),
             start: None,
             end: None,
             start_sec: None,
             end_sec: None,
-            total: ObjectStats{
-                requests: 0,
-                request_time: 0,
-                sent_bytes: 0
-                },
-            clients: HashMap::with_capacity(100),
+            total: ObjectStats::new(),
+            clients: TopK::new(options.clients_capacity),
             hosts: HashMap::with_capacity(1),
             methods: HashMap::with_capacity(3), // GET, POST, HEAD
-            paths: HashMap::with_capacity(20),
+            paths: TopK::new(options.paths_capacity),
             statuses: HashMap::with_capacity(10),
-            referers: HashMap::with_capacity(100),
-            user_agents: HashMap::with_capacity(100),
-            hours: HashMap::with_capacity(24),
+            referers: TopK::new(options.referers_capacity),
+            user_agents: TopK::new(options.user_agents_capacity),
             dates: HashMap::with_capacity(2),
             users: HashMap::with_capacity(1),
+            status_paths: HashMap::with_capacity(10),
+            paths_capacity: options.paths_capacity,
+            broken_links: BrokenLinks::new(options.paths_capacity),
+            unique_clients: UniqueClients::new(options.unique_error,
+                                               options.paths_capacity),
+            time_series: options.resolution.map(|resolution|
+                TimeSeries::new(resolution)),
+            heatmap: Heatmap::new(),
+            group_by: match options.group_by {
+                Some(ref fields) => fields.clone(),
+                None => Vec::new()
+            },
+            groups: options.group_by.as_ref().map(|_|
+                TopK::new(options.paths_capacity)),
+            routes: options.routes.clone(),
+            path_tree: options.tree_depth.map(|depth| PathTree::new(depth)),
+            content: ContentStats::new(),
+            params: ParamStats::new(options.query_params.as_slice(),
+                                    options.paths_capacity),
+            keywords: KeywordStats::new(options.referers_capacity),
+            referer_domains: RefererStats::new(options.referers_capacity,
+                                               options.registrable_domains),
+            hotlinks: Hotlinks::new(options.paths_capacity,
+                                    options.registrable_domains),
+            classifier: classifier,
+            browsers: HashMap::new(),
+            operating_systems: HashMap::new(),
+            devices: HashMap::with_capacity(4),
+            bots: BotStats::new(options.user_agents_capacity),
+            full_urls: options.full_urls,
+            host_options: options.per_host.map(|_|
+                box StatsOptions{per_host: None, ..options.clone()}),
+            host_stats: TopK::new(options.per_host.unwrap_or(0)),
+            networks: if options.network_prefixes.is_some()
+                    || !options.networks.is_empty() {
+                Some(NetworkStats::new(options.network_prefixes,
+                                       options.networks.clone(),
+                                       options.clients_capacity))
+            } else {
+                None
+            },
             }
     }
 }
@@ -67,20 +258,91 @@ impl LogStats {
 impl LogProcessor for LogStats {
     #[inline]
     fn process(&mut self, record: HTTPLogRecord) {
+        // Nested stats get the original record since routes are normalized
+        // by each stats
+        match self.host_options {
+            Some(ref options) => {
+                // A host replacing the least frequent one starts with
+                // empty stats
+                let host = record.host.to_string();
+                let classifier = &self.classifier;
+                let stats = self.host_stats.update_with(host, ||
+                    box LogStats::with_classifier(&**options,
+                                                  classifier.clone()));
+                stats.process(record.clone());
+            },
+            None => {}
+        }
+        let route = self.routes.as_ref().map(|routes|
+            routes.normalize(record.path));
+        let record = match route {
+            Some(ref route) => HTTPLogRecord{path: route.as_slice(), ..record},
+            None => record
+        };
         update_interval(self, &record.local_time);
-        update_totals(&mut self.total, &record);
-        update(&mut self.clients, record.remote_addr.to_string(), &record);
+        self.total.update(&record);
+        match self.groups {
+            Some(ref mut groups) => {
+                self.unique_clients.update_total(record.remote_addr);
+                update_top(groups, group_key(self.group_by.as_slice(), &record),
+                           &record);
+                return;
+            },
+            None => {}
+        }
+        update_top(&mut self.clients, record.remote_addr.to_string(),
+                   &record);
+        match self.networks {
+            Some(ref mut networks) => networks.update(&record),
+            None => {}
+        }
         update(&mut self.hosts, record.host.to_string(), &record);
         update(&mut self.methods, record.method.to_string(), &record);
-        update(&mut self.paths, record.path.to_string(), &record);
+        let path = if self.full_urls {
+            full_url(record.scheme, record.host, record.server_port,
+                     record.path)
+        } else {
+            record.path.to_string()
+        };
+        update_top(&mut self.paths, path.clone(), &record);
+        match self.path_tree {
+            Some(ref mut path_tree) => path_tree.update(&record),
+            None => {}
+        }
+        self.content.update(&record);
+        self.params.update(&record);
         update(&mut self.statuses, record.status, &record);
-        update(&mut self.referers, record.referer.to_string(), &record);
-        update(&mut self.user_agents, record.user_agent.to_string(), &record);
-        update(&mut self.hours, record.local_time.tm_hour as u8, &record);
-        update(&mut self.dates,
-            record.local_time.strftime("%Y-%m-%d").unwrap().to_string(),
-            &record);
+        update_top(&mut self.referers, record.referer.to_string(), &record);
+        self.keywords.update(&record);
+        self.referer_domains.update(&record);
+        self.hotlinks.update(&record);
+        update_top(&mut self.user_agents, record.user_agent.to_string(),
+                   &record);
+        {
+            let mut classifier = self.classifier.borrow_mut();
+            let user_agent = classifier.classify(record.user_agent);
+            update(&mut self.browsers, user_agent.browser.clone(), &record);
+            update(&mut self.operating_systems, user_agent.os.clone(),
+                   &record);
+            update(&mut self.devices, user_agent.device.clone(), &record);
+        }
+        self.bots.update(&record);
+        let date = record.local_time.strftime("%Y-%m-%d").unwrap().to_string();
+        self.unique_clients.update(date.as_slice(), &record);
+        self.heatmap.update(date.as_slice(), &record.local_time);
+        update(&mut self.dates, date, &record);
         update(&mut self.users, record.user.to_string(), &record);
+        let status_paths = match self.status_paths.entry(record.status) {
+            Entry::Vacant(entry) => entry.set(TopK::new(self.paths_capacity)),
+            Entry::Occupied(entry) => entry.into_mut()
+        };
+        update_top(status_paths, path, &record);
+        self.broken_links.update(&record);
+        match self.time_series {
+            Some(ref mut time_series) =>
+                time_series.update(&record.local_time, &record),
+            None => {}
+        }
     }
 }
 
@@ -103,28 +365,95 @@ fn update_interval(stats: &mut LogStats, current: &Tm) {
     }
 }
 
-#[inline]
-fn update_totals(totals: &mut ObjectStats,  record: &HTTPLogRecord) {
-    totals.requests += 1;
-    totals.request_time += record.request_time;
-    totals.sent_bytes += record.sent_bytes;
-}
-
 #[inline]
 fn update<T: Eq + Hash>(mapping: &mut StatsMap<T>, key: T,
         record: &HTTPLogRecord) {
     match mapping.entry(key) {
         Entry::Vacant(entry) => {
-            entry.set(ObjectStats{requests: 1,
-                                  request_time: record.request_time,
-                                  sent_bytes: record.sent_bytes,
-                                  });
+            let mut stats = ObjectStats::new();
+            stats.update(record);
+            entry.set(stats);
         },
         Entry::Occupied(mut entry) => {
-            let stats = entry.get_mut();
-            stats.requests += 1;
-            stats.request_time += record.request_time;
-            stats.sent_bytes += record.sent_bytes;
+            entry.get_mut().update(record);
         }
     };
 }
+
+// Space separated values of the fields
+#[inline]
+fn group_key(fields: &[Field], record: &HTTPLogRecord) -> String {
+    let mut key = String::new();
+    for (i, field) in fields.iter().enumerate() {
+        if i != 0 {
+            key.push(' ');
+        }
+        key.push_str(field.value(record).to_string().as_slice());
+    }
+    key
+}
+
+#[inline]
+fn update_top<T: Eq + Hash + Clone>(top: &mut TopMap<T>, key: T,
+        record: &HTTPLogRecord) {
+    top.update(key, ObjectStats::new()).update(record);
+}
+
+#[inline]
+fn saturating_sub(a: u64, b: u64) -> u64 {
+    if a > b { a - b } else { 0 }
+}
+
+/*
+ * Tests
+ */
+#[cfg(test)]
+mod test {
+    use log::{HTTPLogRecord, LogProcessor, test_record};
+    use timezone::TimeZone;
+    use useragent::Classifier;
+
+    use super::{LogStats, StatsOptions};
+
+    fn options(per_host: Option<uint>) -> StatsOptions {
+        StatsOptions{
+            unique_error: 0.02,
+            clients_capacity: 0,
+            paths_capacity: 0,
+            referers_capacity: 0,
+            user_agents_capacity: 0,
+            resolution: None,
+            timezone: TimeZone::Original,
+            group_by: None,
+            routes: None,
+            tree_depth: None,
+            query_params: Vec::new(),
+            registrable_domains: false,
+            classifier: Classifier::new(),
+            full_urls: false,
+            per_host: per_host,
+            network_prefixes: None,
+            networks: Vec::new(),
+            }
+    }
+
+    #[test]
+    fn test_per_host() {
+        let mut stats = LogStats::new(&options(Some(2)));
+        for &host in ["a.com", "a.com", "b.com", "a.com", "c.com"].iter() {
+            stats.process(HTTPLogRecord{host: host, ..test_record()});
+        }
+        assert_eq!(5, stats.total.requests);
+        // Only the top hosts keep nested stats, c.com replaces b.com
+        let hosts = stats.host_stats.items();
+        assert_eq!(2, hosts.len());
+        for &(host, nested, error) in hosts.iter() {
+            match host.as_slice() {
+                "a.com" => assert_eq!((3, 0), (nested.total.requests, error)),
+                "c.com" => assert_eq!((1, 1), (nested.total.requests, error)),
+                _ => panic!("Unexpected host {}", host)
+            }
+            assert!(nested.host_stats.items().is_empty());
+        }
+    }
+}
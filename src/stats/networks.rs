@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::io::net::ip::{IpAddr, Ipv4Addr};
+
+use cidr::{Cidr, network};
+use log::HTTPLogRecord;
+
+use super::{StatsMap, TopMap, update, update_top};
+use super::topk::TopK;
+
+
+// Clients rolled up into networks by prefix length and into named networks
+pub struct NetworkStats {
+    pub prefixes: Option<(uint, uint)>,
+    named: Vec<(String, Cidr)>,
+    pub networks: TopMap<String>,
+    pub named_networks: StatsMap<String>,
+}
+
+impl NetworkStats {
+    pub fn new(prefixes: Option<(uint, uint)>, named: Vec<(String, Cidr)>,
+               capacity: uint) -> NetworkStats {
+        NetworkStats{
+            prefixes: prefixes,
+            named: named,
+            networks: TopK::new(capacity),
+            named_networks: HashMap::new(),
+            }
+    }
+
+    #[inline]
+    pub fn update(&mut self, record: &HTTPLogRecord) {
+        let addr = match record.remote_addr.parse::<IpAddr>() {
+            Some(addr) => addr,
+            None => return
+        };
+        match self.prefixes {
+            Some((ipv4_prefix, ipv6_prefix)) => {
+                let prefix = match addr {
+                    Ipv4Addr(..) => ipv4_prefix,
+                    _ => ipv6_prefix
+                };
+                let key = format!("{}/{}", network(&addr, prefix), prefix);
+                update_top(&mut self.networks, key, record);
+            },
+            None => {}
+        }
+        if self.named.is_empty() {
+            return;
+        }
+        let name = match self.named.iter().find(|&&(_, ref cidr)|
+                                                 cidr.contains(&addr)) {
+            Some(&(ref name, _)) => name.clone(),
+            None => "Other".to_string()
+        };
+        update(&mut self.named_networks, name, record);
+    }
+}
+
+/*
+ * Tests
+ */
+#[cfg(test)]
+mod test {
+    use std::io::net::ip::IpAddr;
+
+    use cidr::Cidr;
+    use log::{HTTPLogRecord, test_record};
+
+    use super::NetworkStats;
+
+    fn update(networks: &mut NetworkStats, addrs: &[&str]) {
+        for addr in addrs.iter() {
+            let record = HTTPLogRecord{remote_addr: *addr, ..test_record()};
+            networks.update(&record);
+        }
+    }
+
+    #[test]
+    fn test_prefixes() {
+        let mut networks = NetworkStats::new(Some((24, 64)), Vec::new(), 0);
+        update(&mut networks, &["10.1.2.3", "10.1.2.200", "10.1.3.1",
+                                "2001:db8::1", "2001:db8::2:1", "unknown"]);
+        let mut counts: Vec<(String, u64)> = networks.networks.items().iter()
+            .map(|&(key, stats, _)| (key.clone(), stats.requests))
+            .collect();
+        counts.sort();
+        let ipv6 = "2001:db8::".parse::<IpAddr>().unwrap();
+        assert_eq!(vec![("10.1.2.0/24".to_string(), 2),
+                        ("10.1.3.0/24".to_string(), 1),
+                        (format!("{}/64", ipv6), 2)], counts);
+        assert!(networks.named_networks.is_empty());
+    }
+
+    #[test]
+    fn test_named() {
+        let named = vec![("office".to_string(),
+                          Cidr::parse("10.1.0.0/16").unwrap()),
+                         ("vpn".to_string(),
+                          Cidr::parse("10.0.0.0/8").unwrap())];
+        let mut networks = NetworkStats::new(None, named, 0);
+        // Unparsable addresses are skipped
+        update(&mut networks, &["10.1.2.3", "10.2.0.1", "10.1.0.1",
+                                "192.168.0.1", "-"]);
+        let count = |name: &str|
+            networks.named_networks.get(&name.to_string())
+                .map(|stats| stats.requests);
+        assert_eq!(Some(2), count("office"));
+        assert_eq!(Some(1), count("vpn"));
+        assert_eq!(Some(1), count("Other"));
+        assert!(networks.networks.items().is_empty());
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+
+use log::HTTPLogRecord;
+use url::query_params;
+
+use super::{ObjectStats, StatsMap, TopMap, update, update_top};
+use super::topk::TopK;
+
+
+// Values of a selected parameter
+pub struct ParamValues {
+    pub name: String,
+    pub stats: ObjectStats,
+    pub values: TopMap<String>,
+}
+
+pub struct PathParams {
+    pub stats: ObjectStats,
+    pub names: StatsMap<String>,
+}
+
+// Query string parameter names, values of the selected parameters and
+// parameter names per path
+pub struct ParamStats {
+    // Requests with a query string
+    pub total: ObjectStats,
+    pub names: TopMap<String>,
+    pub values: Vec<ParamValues>,
+    pub paths: TopK<String, PathParams>,
+}
+
+impl ParamStats {
+    pub fn new(selected: &[String], capacity: uint) -> ParamStats {
+        ParamStats{
+            total: ObjectStats::new(),
+            names: TopK::new(capacity),
+            values: selected.iter().map(|name| ParamValues{
+                name: name.clone(),
+                stats: ObjectStats::new(),
+                values: TopK::new(capacity),
+                }).collect(),
+            paths: TopK::new(capacity),
+            }
+    }
+
+    #[inline]
+    pub fn update(&mut self, record: &HTTPLogRecord) {
+        if record.query.is_empty() {
+            return;
+        }
+        let params = query_params(record.query);
+        if params.is_empty() {
+            return;
+        }
+        self.total.update(record);
+        let path = self.paths.update(record.path.to_string(), PathParams{
+            stats: ObjectStats::new(),
+            names: HashMap::new(),
+            });
+        path.stats.update(record);
+        for (i, &(ref name, ref value)) in params.iter().enumerate() {
+            // Repeated parameters like a=1&a=2 are counted once
+            if params.slice_to(i).iter().any(|&(ref n, _)| n == name) {
+                continue;
+            }
+            update_top(&mut self.names, name.clone(), record);
+            update(&mut path.names, name.clone(), record);
+            for selected in self.values.iter_mut() {
+                if selected.name == *name {
+                    selected.stats.update(record);
+                    update_top(&mut selected.values, value.clone(), record);
+                }
+            }
+        }
+    }
+}
@@ -1,11 +1,36 @@
+use std::cmp;
 use std::fmt::Show;
 use std::hash::Hash;
 
-use time::Tm;
+use time::{Tm, Timespec, at_utc};
 
-use super::{LogStats, StatsItem, StatsMap, ObjectStats};
-use super::utils::{format_duration, format_bytes, format_number};
+use log::HTTPStatus;
+use log::content::Category;
+use timezone::TimeZone;
 
+use super::{LogStats, StatsItem, StatsMap, TopMap, ObjectStats};
+use super::bots::{BotStats, Crawler};
+use super::content::ContentStats;
+use super::heatmap::{Heatmap, HourCounts};
+use super::hotlinks::{Hotlinks, HotlinkDomain};
+use super::keywords::KeywordStats;
+use super::links::{BrokenLinks, BrokenLink};
+use super::networks::NetworkStats;
+use super::params::{ParamStats, PathParams};
+use super::referers::RefererStats;
+use super::timeseries::{TimeSeries, TimeBucket};
+use super::tree::{PathTree, PathNode};
+use super::unique::{UniqueClients, UniqueStats};
+use super::utils::{format_duration, format_bytes, format_number,
+                   format_percent};
+
+
+pub struct PrinterOptions {
+    // Number of items to print in each section
+    pub limit: uint,
+    // Statuses to print top paths for, all error statuses if None
+    pub statuses: Option<Vec<HTTPStatus>>,
+}
 
 pub struct LogStatsPrinter<'r> {
     stats: &'r LogStats,
@@ -16,23 +41,125 @@ impl<'r> LogStatsPrinter<'r> {
         LogStatsPrinter{stats: stats}
     }
 
-    pub fn print(&self, limit: uint) {
-        print_totals(&self.stats.total, &self.stats.start, &self.stats.end);
-        print(&self.stats.hosts, "Requests", "Hosts", limit);
-        print(&self.stats.dates, "Requests", "Dates", limit);
-        print(&self.stats.users, "Requests", "Users", limit);
-        print(&self.stats.clients, "Requests", "Clients", limit);
-        print(&self.stats.hours, "Requests", "Hours", limit);
-        print(&self.stats.paths, "Requests", "Paths", limit);
-        print(&self.stats.methods, "Requests", "Methods", limit);
-        print(&self.stats.statuses, "Requests", "Statuses", limit);
-        print(&self.stats.referers, "Requests", "Referers", limit);
-        print(&self.stats.user_agents, "Requests", "User agents", limit);
+    // Report for all hosts followed by reports for top hosts in the per host
+    // mode, the number of hosts is given by the per host option
+    pub fn print(&self, options: &PrinterOptions) {
+        self.print_report(options);
+        let mut hosts = self.stats.host_stats.items();
+        hosts.sort_by(|&(_, a, a_error), &(_, b, b_error)|
+                      (b.total.requests + b_error)
+                      .cmp(&(a.total.requests + a_error)));
+        for &(host, stats, error) in hosts.iter() {
+            println!("\n\n{}\n\
+                      #####################################################\
+                      ############################", host);
+            // Requests before the host was monitored are missing
+            if error > 0 {
+                println!("Missing up to {} earliest requests", error);
+            }
+            LogStatsPrinter::new(&**stats).print_report(options);
+        }
+    }
+
+    fn print_report(&self, options: &PrinterOptions) {
+        let limit = options.limit;
+        let total = &self.stats.total;
+        print_totals(total, &self.stats.unique_clients, &self.stats.start,
+                     &self.stats.end, &self.stats.timezone);
+        match self.stats.groups {
+            Some(ref groups) => {
+                let names: Vec<&str> = self.stats.group_by.iter()
+                    .map(|field| field.name())
+                    .collect();
+                let key_title = names.connect(", ");
+                print_top(groups, "Requests", key_title.as_slice(), limit,
+                          total);
+                return;
+            },
+            None => {}
+        }
+        print(&self.stats.hosts, "Requests", "Hosts", limit, total);
+        print(&self.stats.dates, "Requests", "Dates", limit, total);
+        match self.stats.time_series {
+            Some(ref time_series) => print_time_series(time_series),
+            None => {}
+        }
+        print(&self.stats.users, "Requests", "Users", limit, total);
+        print_top(&self.stats.clients, "Requests", "Clients", limit, total);
+        match self.stats.networks {
+            Some(ref networks) => print_networks(networks, limit, total),
+            None => {}
+        }
+        print_heatmap(&self.stats.heatmap);
+        print_top(&self.stats.paths, "Requests", self.paths_title(), limit,
+                  total);
+        match self.stats.path_tree {
+            Some(ref path_tree) => print_path_tree(path_tree, limit),
+            None => {}
+        }
+        print_content(&self.stats.content, limit, total);
+        print_params(&self.stats.params, limit);
+        print(&self.stats.methods, "Requests", "Methods", limit, total);
+        print(&self.stats.statuses, "Requests", "Statuses", limit, total);
+        print_top(&self.stats.referers, "Requests", "Referers", limit,
+                  total);
+        print_referer_domains(&self.stats.referer_domains, limit);
+        print_keywords(&self.stats.keywords, limit);
+        print_top(&self.stats.user_agents, "Requests", "User agents", limit,
+                  total);
+        print(&self.stats.browsers, "Requests", "Browsers", limit, total);
+        print(&self.stats.operating_systems, "Requests", "Operating systems",
+              limit, total);
+        print(&self.stats.devices, "Requests", "Devices", limit, total);
+        print_bots(&self.stats.bots, limit, total);
+        self.print_status_paths(&options.statuses, limit);
+        print_broken_links(&self.stats.broken_links, limit);
+        print_hotlinks(&self.stats.hotlinks, limit);
+        let unique = &self.stats.unique_clients;
+        print_unique(unique.dates.iter().collect(), "Dates", limit,
+                     unique.error);
+        print_unique(unique.hosts.iter().collect(), "Hosts", limit,
+                     unique.error);
+        print_unique(unique.paths.items().into_iter()
+                         .map(|(path, stats, _)| (path, stats)).collect(),
+                     "Paths", limit, unique.error);
+    }
+
+    #[inline]
+    fn paths_title(&self) -> &'static str {
+        if self.stats.full_urls { "URLs" } else { "Paths" }
+    }
+
+    fn print_status_paths(&self, statuses: &Option<Vec<HTTPStatus>>,
+            limit: uint) {
+        let selected = match *statuses {
+            Some(ref selected) => selected.clone(),
+            None => {
+                let mut items: Vec<StatsItem<HTTPStatus>> =
+                    self.stats.statuses.iter()
+                        .filter(|&(status, _)| status.is_error())
+                        .collect();
+                items.sort_by(|&(_, a), &(_, b)| b.requests.cmp(&a.requests));
+                items.iter().map(|&(status, _)| *status).collect()
+            }
+        };
+        for status in selected.iter() {
+            match (self.stats.status_paths.get(status),
+                   self.stats.statuses.get(status)) {
+                (Some(paths), Some(total)) => {
+                    let title = format!("{} requests", *status);
+                    print_top(paths, title.as_slice(), self.paths_title(),
+                              limit, total);
+                },
+                _ => {}
+            }
+        }
     }
 }
 
 #[inline]
-fn print_totals(totals: &ObjectStats, start: &Option<Tm>, end: &Option<Tm>) {
+fn print_totals(totals: &ObjectStats, unique: &UniqueClients,
+        start: &Option<Tm>, end: &Option<Tm>, timezone: &TimeZone) {
     let start_date = match *start {
         None => "-".to_string(),
         Some(ref s) => s.strftime("%Y-%m-%d").unwrap().to_string()
@@ -43,43 +170,429 @@ fn print_totals(totals: &ObjectStats, start: &Option<Tm>, end: &Option<Tm>) {
     };
     println!("Totals\n\
               =====================================================\
-              ============================\n\
-              Period                                                    \
-              Requests Duration Bytes\n\
-              -----------------------------------------------------\
+              ============================");
+    let period = format!("Period ({} time)", timezone.name());
+    println!("{: <48} {: >8} {: >8} {: >8} {: >5}",
+             period, "Clients", "Requests", "Duration", "Bytes");
+    println!("-----------------------------------------------------\
               ----------------------------");
-    println!("{: <10} - {: >10}                                   \
-              {: >8} {: >8} {: >5}",
+    println!("{: <10} - {: <35} {: >8} {: >8} {: >8} {: >5}",
              start_date,
              end_date,
+             format_number(unique.total.count()),
              format_number(totals.requests),
              format_duration(totals.request_time),
              format_bytes(totals.sent_bytes));
 }
 
+#[inline]
+fn print_time_series(time_series: &TimeSeries) {
+    println!("\nRequests over time ({})\n\
+              =====================================================\
+              ============================",
+              format_duration(time_series.resolution as u64 * 1000));
+    println!("{: <16} {: >8} {: >8} {: >5} {: >6} {: >5} {: >6} {: >6} {: >6}",
+             "Time", "Requests", "Duration", "Bytes", "Errors", "%",
+             "p50", "p95", "p99");
+    println!("-----------------------------------------------------\
+              ----------------------------");
+    let mut buckets: Vec<(&i64, &TimeBucket)> =
+        time_series.buckets.iter().collect();
+    buckets.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+    // Single empty buckets are printed to show gaps, longer runs of empty
+    // buckets are collapsed into one line
+    let empty = TimeBucket::new();
+    let mut next = match buckets.as_slice().first() {
+        Some(&(&first, _)) => first,
+        None => return
+    };
+    for &(&start, bucket) in buckets.iter() {
+        let gap = (start - next) / time_series.resolution;
+        if gap == 1 {
+            print_time_bucket(next, &empty);
+        } else if gap > 1 {
+            println!("{: <16} {} empty intervals",
+                     format_bucket_time(next), format_number(gap as u64));
+        }
+        print_time_bucket(start, bucket);
+        next = start + time_series.resolution;
+    }
+}
+
+#[inline]
+fn format_bucket_time(start: i64) -> String {
+    at_utc(Timespec::new(start, 0)).strftime("%Y-%m-%d %H:%M").unwrap()
+        .to_string()
+}
+
+fn print_time_bucket(start: i64, bucket: &TimeBucket) {
+    println!("{: <16} {: >8} {: >8} {: >5} {: >6} {: >5} {: >6} {: >6} {: >6}",
+             format_bucket_time(start),
+             format_number(bucket.stats.requests),
+             format_duration(bucket.stats.request_time),
+             format_bytes(bucket.stats.sent_bytes),
+             format_number(bucket.stats.errors()),
+             format_percent(bucket.stats.errors(), bucket.stats.requests),
+             format_duration(bucket.request_times.percentile(50.0)),
+             format_duration(bucket.request_times.percentile(95.0)),
+             format_duration(bucket.request_times.percentile(99.0)));
+}
+
+fn print_path_tree(path_tree: &PathTree, limit: uint) {
+    let heading = format!("Path tree (depth {})", path_tree.depth);
+    print_header(heading.as_slice(), "Prefix");
+    print_path_node(&path_tree.root, "", 0, limit, &path_tree.root.stats);
+}
+
+// Top subdirectories are printed under the parent indented by level
+fn print_path_node(node: &PathNode, name: &str, level: uint, limit: uint,
+        total: &ObjectStats) {
+    let key = format!("{}{}/", String::from_char(level * 2, ' '), name);
+    print_row(&key, &node.stats, total);
+    let mut children: Vec<(&String, &PathNode)> = node.children.iter()
+        .collect();
+    children.sort_by(|&(_, a), &(_, b)|
+        b.stats.requests.cmp(&a.stats.requests));
+    for &(name, child) in children.iter().take(limit) {
+        print_path_node(child, name.as_slice(), level + 1, limit, total);
+    }
+}
+
+fn print_content(content: &ContentStats, limit: uint, total: &ObjectStats) {
+    print(&content.extensions, "Requests", "Extensions", limit, total);
+    print(&content.categories, "Requests", "Categories", limit, total);
+    println!("\nCaching by category\n\
+              =====================================================\
+              ============================");
+    println!("{: <28} {: >8} {: >8} {: >5}", "Category", "200", "304", "%");
+    println!("-----------------------------------------------------\
+              ----------------------------");
+    let mut categories: Vec<StatsItem<Category>> = content.categories.iter()
+        .collect();
+    categories.sort_by(|&(_, a), &(_, b)| b.requests.cmp(&a.requests));
+    for &(category, _) in categories.iter() {
+        let cache = content.cache.get(category).unwrap();
+        println!("{: <28} {: >8} {: >8} {: >5}",
+                 *category,
+                 format_number(cache.ok),
+                 format_number(cache.not_modified),
+                 format_percent(cache.not_modified,
+                                cache.ok + cache.not_modified));
+    }
+}
+
+// Heatmap shades from the lowest to the highest number of requests
+static SHADES: [char, ..10] =
+    [' ', '.', ':', '-', '=', '+', '*', '#', '%', '@'];
+// Weekdays in the display order with tm_wday indexes
+static WEEKDAYS: [(uint, &'static str), ..7] = [
+    (1, "Monday"), (2, "Tuesday"), (3, "Wednesday"), (4, "Thursday"),
+    (5, "Friday"), (6, "Saturday"), (0, "Sunday")];
+
+#[inline]
+fn print_heatmap(heatmap: &Heatmap) {
+    let weekdays: Vec<(&str, &HourCounts)> = WEEKDAYS.iter()
+        .map(|&(wday, name)| (name, &heatmap.weekdays[wday]))
+        .collect();
+    print_hours(weekdays, "Weekdays");
+    let mut dates: Vec<(&str, &HourCounts)> = heatmap.dates.iter()
+        .map(|(date, hours)| (date.as_slice(), hours))
+        .collect();
+    dates.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+    print_hours(dates, "Dates");
+}
+
+#[inline]
+fn print_hours(rows: Vec<(&str, &HourCounts)>, key_title: &str) {
+    let max = rows.iter()
+        .flat_map(|&(_, hours)| hours.iter())
+        .fold(0, |max, &n| cmp::max(max, n));
+    println!("\nRequests by {} and Hours (max {} per hour)\n\
+              =====================================================\
+              ============================",
+              key_title, format_number(max));
+    let mut hours_title = String::with_capacity(48);
+    for hour in range(0u, 12) {
+        hours_title.push_str(format!("{: <4}", hour * 2).as_slice());
+    }
+    println!("{: <10} {} {: >8}", key_title, hours_title, "Requests");
+    println!("-----------------------------------------------------\
+              ----------------------------");
+    for &(key, hours) in rows.iter() {
+        let mut cells = String::with_capacity(48);
+        for &n in hours.iter() {
+            let shade = if max == 0 {
+                SHADES[0]
+            } else {
+                // Any request is visible, the busiest hour gets the darkest
+                // shade
+                let last = SHADES.len() as u64 - 1;
+                SHADES[((n * last + max - 1) / max) as uint]
+            };
+            cells.push(shade);
+            cells.push(shade);
+        }
+        let requests = hours.iter().fold(0, |sum, &n| sum + n);
+        println!("{: <10.10} {} {: >8}", key, cells, format_number(requests));
+    }
+}
+
+// Number of referers to print for each broken link, parameters for each
+// path, assets for each hotlinking domain and paths for each crawler
+static SUB_ITEMS: uint = 3u;
+
+#[inline]
+fn print_broken_links(broken_links: &BrokenLinks, limit: uint) {
+    let mut items: Vec<(&String, &BrokenLink, u64)> =
+        broken_links.links.items();
+    items.sort_by(|&(_, a, a_error), &(_, b, b_error)|
+                  (b.stats.requests + b_error)
+                      .cmp(&(a.stats.requests + a_error)));
+    let heading = format!("Broken links (top {})", limit);
+    print_header(heading.as_slice(), "Paths");
+    for &(path, link, _) in items.iter().take(limit) {
+        print_row(path, &link.stats, &broken_links.total);
+        print_sub_items(&link.internal, "internal");
+        print_sub_items(&link.external, "external");
+    }
+}
+
+fn print_networks(networks: &NetworkStats, limit: uint, total: &ObjectStats) {
+    if networks.prefixes.is_some() {
+        print_top(&networks.networks, "Requests", "Networks", limit, total);
+    }
+    if !networks.named_networks.is_empty() {
+        print(&networks.named_networks, "Requests", "Named networks", limit,
+              total);
+    }
+}
+
+fn print_bots(bots: &BotStats, limit: uint, total: &ObjectStats) {
+    print(&bots.traffic, "Requests", "Traffic", limit, total);
+    let mut items: Vec<(&String, &Crawler, u64)> = bots.crawlers.items();
+    items.sort_by(|&(_, a, a_error), &(_, b, b_error)|
+                  (b.stats.requests + b_error)
+                      .cmp(&(a.stats.requests + a_error)));
+    let heading = format!("Crawlers (top {})", limit);
+    print_header(heading.as_slice(), "Bots");
+    for &(name, crawler, _) in items.iter().take(limit) {
+        print_row(name, &crawler.stats, &bots.total);
+        print_sub_items(&crawler.paths, "path");
+    }
+}
+
+fn print_hotlinks(hotlinks: &Hotlinks, limit: uint) {
+    let mut items: Vec<(&String, &HotlinkDomain, u64)> =
+        hotlinks.domains.items();
+    items.sort_by(|&(_, a, a_error), &(_, b, b_error)|
+                  (b.stats.requests + b_error)
+                      .cmp(&(a.stats.requests + a_error)));
+    let heading = format!("Hotlinking (top {})", limit);
+    print_header(heading.as_slice(), "Domains");
+    for &(domain, hotlink, _) in items.iter().take(limit) {
+        print_row(domain, &hotlink.stats, &hotlinks.total);
+        print_top_sub_items(&hotlink.assets, "asset");
+    }
+    print_top(&hotlinks.assets, "Hotlinked requests", "Assets", limit,
+              &hotlinks.total);
+}
+
+#[inline]
+fn print_sub_items(mapping: &StatsMap<String>, kind: &str) {
+    let mut items: Vec<StatsItem<String>> = mapping.iter().collect();
+    items.sort_by(|&(_, a), &(_, b)| b.requests.cmp(&a.requests));
+    for &(key, stats) in items.iter().take(SUB_ITEMS) {
+        println!("    {: <8} {: <57.57} {: >8}",
+                 kind, *key, format_number(stats.requests));
+    }
+}
+
+// Like print_sub_items but ~ marks estimated requests
+fn print_top_sub_items(top: &TopMap<String>, kind: &str) {
+    let mut items = top.items();
+    items.sort_by(|&(_, a, a_error), &(_, b, b_error)|
+                  (b.requests + b_error).cmp(&(a.requests + a_error)));
+    for &(key, stats, error) in items.iter().take(SUB_ITEMS) {
+        let requests = if error > 0 {
+            format!("~{}", format_number(stats.requests + error))
+        } else {
+            format_number(stats.requests)
+        };
+        println!("    {: <8} {: <57.57} {: >8}", kind, *key, requests);
+    }
+}
+
+fn print_referer_domains(referers: &RefererStats, limit: uint) {
+    print_top(&referers.domains, "Requests", "Referring domains", limit,
+              &referers.external);
+    print_top(&referers.internal, "Requests", "Internal referers", limit,
+              &referers.internal_total);
+}
+
+fn print_keywords(keywords: &KeywordStats, limit: uint) {
+    print_top(&keywords.search, "Requests", "Keywords", limit,
+              &keywords.search_total);
+    print_top(&keywords.site, "Requests", "Site search keywords", limit,
+              &keywords.site_total);
+}
+
+fn print_params(params: &ParamStats, limit: uint) {
+    print_top(&params.names, "Requests", "Query parameters", limit,
+              &params.total);
+    for selected in params.values.iter() {
+        let title = format!("Requests with {}", selected.name);
+        print_top(&selected.values, title.as_slice(), "Values", limit,
+                  &selected.stats);
+    }
+    let mut items: Vec<(&String, &PathParams, u64)> = params.paths.items();
+    items.sort_by(|&(_, a, a_error), &(_, b, b_error)|
+                  (b.stats.requests + b_error)
+                      .cmp(&(a.stats.requests + a_error)));
+    let heading = format!("Query parameters by paths (top {})", limit);
+    print_header(heading.as_slice(), "Paths");
+    for &(path, path_params, _) in items.iter().take(limit) {
+        print_row(path, &path_params.stats, &params.total);
+        print_sub_items(&path_params.names, "param");
+    }
+}
+
+#[inline]
+fn print_unique(unique: Vec<(&String, &UniqueStats)>, key_title: &str,
+        limit: uint, error: f64) {
+    let mut items: Vec<(&String, &UniqueStats, u64)> = unique.into_iter()
+        .map(|(key, stats)| (key, stats, stats.clients.count()))
+        .collect();
+    items.sort_by(|&(_, _, a), &(_, _, b)| b.cmp(&a));
+    println!("\nUnique clients by {} (top {}, error {})\n\
+              =====================================================\
+              ============================",
+              key_title, limit,
+              format_percent((error * 10000.0) as u64, 10000));
+    println!("{: <52} {: >8} {: >8} {: >10}",
+             key_title, "Requests", "Clients", "Req/client");
+    println!("-----------------------------------------------------\
+              ----------------------------");
+    for &(key, stats, clients) in items.iter().take(limit) {
+        let per_client = if clients != 0 {
+            stats.requests as f64 / clients as f64
+        } else {
+            0.0
+        };
+        println!("{: <52.52} {: >8} {: >8} {: >10.1}",
+                 *key,
+                 format_number(stats.requests),
+                 format_number(clients),
+                 per_client);
+    }
+}
+
 #[inline]
 fn print<T: Eq + Hash + Show>(mapping: &StatsMap<T>, title: &str,
-        key_title: &str, limit: uint) {
+        key_title: &str, limit: uint, total: &ObjectStats) {
     let mut items: Vec<StatsItem<T>> = mapping.iter().collect();
     items.sort_by(|&(_, a), &(_, b)| b.requests.cmp(&a.requests));
-    print_sorted(items, title, key_title, limit);
+    let heading = format!("{} by {} (top {})", title, key_title, limit);
+    print_sorted(items, heading.as_slice(), key_title, limit, total);
+}
+
+#[inline]
+fn print_top<T: Eq + Hash + Clone + Show>(top: &TopMap<T>, title: &str,
+        key_title: &str, limit: uint, total: &ObjectStats) {
+    // Keys are ordered by estimated requests, monitored requests plus the
+    // count error
+    let mut items = top.items();
+    items.sort_by(|&(_, a, a_error), &(_, b, b_error)|
+                  (b.requests + b_error).cmp(&(a.requests + a_error)));
+    let heading = if items.iter().take(limit).any(|&(_, _, error)| error > 0) {
+        format!("{} by {} (top {}, ~ marks estimated requests, \u00b1 their \
+                 maximal error)", title, key_title, limit)
+    } else {
+        format!("{} by {} (top {})", title, key_title, limit)
+    };
+    print_header(heading.as_slice(), key_title);
+    let mut other = total.clone();
+    for &(key, stats, error) in items.iter().take(limit) {
+        if error > 0 {
+            print_estimated_row(key, stats, error, total);
+        } else {
+            print_row(key, stats, total);
+        }
+        other.subtract(stats);
+    }
+    if other.requests > 0 {
+        print_row(&"Other", &other, total);
+    }
 }
 
 #[inline]
 fn print_sorted<T: Show>(sorted: Vec<StatsItem<T>>,
-        title: &str, key_title: &str, limit: uint) {
-    println!("\n{} by {} (top {})\n\
-              =====================================================\
-              ============================\n\
-              {: <57} Requests Duration Bytes\n\
-              -----------------------------------------------------\
-              ----------------------------",
-              title, key_title, limit, key_title);
-    for &(client, stats) in sorted.iter().take(limit) {
-        println!("{: <60.60} {: >5} {: >8} {: >5}",
-                 *client,
-                 format_number(stats.requests),
-                 format_duration(stats.request_time),
-                 format_bytes(stats.sent_bytes));
+        heading: &str, key_title: &str, limit: uint, total: &ObjectStats) {
+    print_header(heading, key_title);
+    // Other is calculated from the total since bounded stats don't keep
+    // all the keys
+    let mut other = total.clone();
+    for &(key, stats) in sorted.iter().take(limit) {
+        print_row(key, stats, total);
+        other.subtract(stats);
+    }
+    if other.requests > 0 {
+        print_row(&"Other", &other, total);
     }
 }
+
+#[inline]
+fn print_header(heading: &str, key_title: &str) {
+    println!("\n{}\n\
+              =====================================================\
+              ============================",
+              heading);
+    println!("{: <60} {: >8} {: >5} {: >6} {: >5} {: >5} {: >5} {: >5} {: >5} \
+              {: >5} {: >5} {: >6}",
+             key_title, "Requests", "%", "Time", "%", "Bytes", "%", "2xx",
+             "3xx", "4xx", "5xx", "Errors");
+    println!("-----------------------------------------------------\
+              ----------------------------");
+}
+
+// Row of an evicted and monitored again key, the request number is the
+// estimate while the other columns are for the counted requests only. The
+// error after the row is the maximal overestimation of the requests.
+fn print_estimated_row<T: Show>(key: &T, stats: &ObjectStats, error: u64,
+        total: &ObjectStats) {
+    let requests = stats.requests + error;
+    println!("{: <60.60} {: >8} {: >5} {: >6} {: >5} {: >5} {: >5} {: >5} \
+              {: >5} {: >5} {: >5} {: >6} \u00b1{}",
+             *key,
+             format!("~{}", format_number(requests)),
+             format_percent(requests, total.requests),
+             format_duration(stats.request_time),
+             format_percent(stats.request_time, total.request_time),
+             format_bytes(stats.sent_bytes),
+             format_percent(stats.sent_bytes, total.sent_bytes),
+             format_percent(stats.status_classes[2], stats.requests),
+             format_percent(stats.status_classes[3], stats.requests),
+             format_percent(stats.status_classes[4], stats.requests),
+             format_percent(stats.status_classes[5], stats.requests),
+             format_number(stats.errors()),
+             format_number(error));
+}
+
+// Status class columns are shares of the key requests, errors are 4xx and
+// 5xx requests
+#[inline]
+fn print_row<T: Show>(key: &T, stats: &ObjectStats, total: &ObjectStats) {
+    println!("{: <60.60} {: >8} {: >5} {: >6} {: >5} {: >5} {: >5} {: >5} \
+              {: >5} {: >5} {: >5} {: >6}",
+             *key,
+             format_number(stats.requests),
+             format_percent(stats.requests, total.requests),
+             format_duration(stats.request_time),
+             format_percent(stats.request_time, total.request_time),
+             format_bytes(stats.sent_bytes),
+             format_percent(stats.sent_bytes, total.sent_bytes),
+             format_percent(stats.status_classes[2], stats.requests),
+             format_percent(stats.status_classes[3], stats.requests),
+             format_percent(stats.status_classes[4], stats.requests),
+             format_percent(stats.status_classes[5], stats.requests),
+             format_number(stats.errors()));
+}
@@ -0,0 +1,257 @@
+use std::cmp;
+use std::cmp::Ordering;
+
+use log::{HTTPLogRecord, LogProcessor};
+use log::field::{Field, FieldValue};
+
+use super::{ObjectStats, StatsOptions};
+use super::histogram::Histogram;
+use super::sql::{Query, Column, Aggregate};
+use super::topk::TopK;
+
+
+// Aggregate state for a column, count and sums of request time and sent
+// bytes are taken from the group stats
+enum State {
+    Stats,
+    Sum(u64),
+    Min(Option<u64>),
+    Max(u64),
+    Percentile(Histogram),
+}
+
+struct Group {
+    stats: ObjectStats,
+    states: Vec<State>,
+}
+
+enum Cell<'r> {
+    Text(&'r str),
+    Number(f64),
+}
+
+// Streaming evaluation of the query with aggregates by groups. Groups are
+// bounded like grouped stats, aggregates of a group evicted and monitored
+// again are for the requests since then only.
+pub struct QueryProcessor {
+    query: Query,
+    groups: TopK<Vec<String>, Group>,
+}
+
+impl QueryProcessor {
+    pub fn new(query: Query, options: &StatsOptions) -> QueryProcessor {
+        QueryProcessor{
+            query: query,
+            groups: TopK::new(options.paths_capacity),
+            }
+    }
+
+    pub fn print(&self) {
+        let mut rows: Vec<Vec<Cell>> = self.groups.items().into_iter()
+            .map(|(values, group, _)| self.cells(values.as_slice(), group))
+            .collect();
+        rows.sort_by(|a, b| self.compare(a.as_slice(), b.as_slice()));
+        let limit = self.query.limit.unwrap_or(rows.len());
+        let formatted: Vec<Vec<String>> = rows.iter().take(limit)
+            .map(|row| row.iter().zip(self.query.columns.iter())
+                 .map(|(cell, column)| format_cell(cell, column))
+                 .collect())
+            .collect();
+        let mut widths: Vec<uint> = self.query.titles.iter()
+            .map(|title| title.as_slice().char_len())
+            .collect();
+        for row in formatted.iter() {
+            for (width, value) in widths.iter_mut().zip(row.iter()) {
+                *width = cmp::max(*width, value.as_slice().char_len());
+            }
+        }
+        let titles: Vec<&str> = self.query.titles.iter()
+            .map(|title| title.as_slice())
+            .collect();
+        print_row(titles.as_slice(), widths.as_slice(),
+                  self.query.columns.as_slice());
+        let rules: Vec<String> = widths.iter()
+            .map(|&width| String::from_char(width, '-'))
+            .collect();
+        let rules: Vec<&str> = rules.iter().map(|rule| rule.as_slice())
+            .collect();
+        print_row(rules.as_slice(), widths.as_slice(),
+                  self.query.columns.as_slice());
+        for row in formatted.iter() {
+            let values: Vec<&str> = row.iter().map(|value| value.as_slice())
+                .collect();
+            print_row(values.as_slice(), widths.as_slice(),
+                      self.query.columns.as_slice());
+        }
+    }
+
+    fn cells<'r>(&self, values: &'r [String], group: &Group) -> Vec<Cell<'r>> {
+        self.query.columns.iter().zip(group.states.iter())
+            .map(|(column, state)| match *column {
+                Column::Value(field) => {
+                    let index = self.query.group_by.iter()
+                        .position(|f| *f == field).unwrap();
+                    Cell::Text(values[index].as_slice())
+                },
+                Column::Aggregate(ref aggregate) =>
+                    Cell::Number(result(aggregate, state, &group.stats))
+            })
+            .collect()
+    }
+
+    fn compare(&self, a: &[Cell], b: &[Cell]) -> Ordering {
+        for order in self.query.order_by.iter() {
+            let ordering = match (&a[order.column], &b[order.column]) {
+                (&Cell::Text(a), &Cell::Text(b)) => a.cmp(b),
+                (&Cell::Number(a), &Cell::Number(b)) =>
+                    a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+                _ => Ordering::Equal
+            };
+            let ordering = if order.descending {
+                ordering.reverse()
+            } else {
+                ordering
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+impl LogProcessor for QueryProcessor {
+    #[inline]
+    fn process(&mut self, record: HTTPLogRecord) {
+        match self.query.filter {
+            Some(ref expr) if !expr.matches(&record) => return,
+            _ => {}
+        }
+        let key: Vec<String> = self.query.group_by.iter()
+            .map(|field| field.value(&record).to_string())
+            .collect();
+        let columns = &self.query.columns;
+        let group = self.groups.update_with(key, || Group{
+            stats: ObjectStats::new(),
+            states: columns.iter().map(new_state).collect(),
+            });
+        group.stats.update(&record);
+        for (column, state) in columns.iter().zip(group.states.iter_mut()) {
+            match *column {
+                Column::Aggregate(ref aggregate) =>
+                    update_state(aggregate, state, &record),
+                Column::Value(_) => {}
+            }
+        }
+    }
+}
+
+fn new_state(column: &Column) -> State {
+    match *column {
+        Column::Aggregate(Aggregate::Sum(field))
+                | Column::Aggregate(Aggregate::Avg(field))
+                if !is_stats_field(field) => State::Sum(0),
+        Column::Aggregate(Aggregate::Min(_)) => State::Min(None),
+        Column::Aggregate(Aggregate::Max(_)) => State::Max(0),
+        Column::Aggregate(Aggregate::Percentile(..)) =>
+            State::Percentile(Histogram::new()),
+        _ => State::Stats
+    }
+}
+
+// Fields summed in ObjectStats
+#[inline]
+fn is_stats_field(field: Field) -> bool {
+    field == Field::RequestTime || field == Field::SentBytes
+}
+
+#[inline]
+fn update_state(aggregate: &Aggregate, state: &mut State,
+        record: &HTTPLogRecord) {
+    let field = match *aggregate {
+        Aggregate::Count => return,
+        Aggregate::Sum(field) | Aggregate::Avg(field) | Aggregate::Min(field)
+            | Aggregate::Max(field) | Aggregate::Percentile(field, _) => field
+    };
+    let value = match field.value(record) {
+        FieldValue::Num(value) => value,
+        // Only numeric fields are allowed in aggregates
+        _ => return
+    };
+    match *state {
+        State::Stats => {},
+        State::Sum(ref mut sum) => *sum += value,
+        State::Min(ref mut min) => {
+            if min.map_or(true, |min| value < min) {
+                *min = Some(value);
+            }
+        },
+        State::Max(ref mut max) => *max = cmp::max(*max, value),
+        State::Percentile(ref mut histogram) => histogram.insert(value)
+    }
+}
+
+fn result(aggregate: &Aggregate, state: &State, stats: &ObjectStats) -> f64 {
+    let sum = |field: Field| match *state {
+        State::Sum(sum) => sum,
+        _ if field == Field::RequestTime => stats.request_time,
+        _ => stats.sent_bytes
+    };
+    match (aggregate, state) {
+        (&Aggregate::Count, _) => stats.requests as f64,
+        (&Aggregate::Sum(field), _) => sum(field) as f64,
+        (&Aggregate::Avg(field), _) =>
+            sum(field) as f64 / stats.requests as f64,
+        (&Aggregate::Min(_), &State::Min(min)) => min.unwrap_or(0) as f64,
+        (&Aggregate::Max(_), &State::Max(max)) => max as f64,
+        (&Aggregate::Percentile(_, percent), &State::Percentile(ref h)) =>
+            h.percentile(percent) as f64,
+        _ => 0.0
+    }
+}
+
+// Request time is printed in seconds like in the log
+fn format_cell(cell: &Cell, column: &Column) -> String {
+    match *cell {
+        Cell::Text(text) => text.to_string(),
+        Cell::Number(n) => {
+            let field = match *column {
+                Column::Aggregate(Aggregate::Sum(field))
+                    | Column::Aggregate(Aggregate::Avg(field))
+                    | Column::Aggregate(Aggregate::Min(field))
+                    | Column::Aggregate(Aggregate::Max(field))
+                    | Column::Aggregate(Aggregate::Percentile(field, _)) =>
+                        Some(field),
+                _ => None
+            };
+            match field {
+                Some(Field::RequestTime) => format!("{:.3}", n / 1000.0),
+                _ if n == n.trunc() => format!("{}", n as u64),
+                _ => format!("{:.2}", n)
+            }
+        }
+    }
+}
+
+// Text columns are aligned to the left and numbers to the right
+fn print_row(values: &[&str], widths: &[uint], columns: &[Column]) {
+    let mut line = String::new();
+    for ((value, &width), column) in values.iter().zip(widths.iter())
+            .zip(columns.iter()) {
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        let padding = String::from_char(width - value.char_len(), ' ');
+        match *column {
+            Column::Value(_) => {
+                line.push_str(*value);
+                line.push_str(padding.as_slice());
+            },
+            Column::Aggregate(_) => {
+                line.push_str(padding.as_slice());
+                line.push_str(*value);
+            }
+        }
+    }
+    println!("{}", line.as_slice().trim_right());
+}
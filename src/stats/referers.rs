@@ -0,0 +1,62 @@
+use std::ascii::AsciiExt;
+
+use log::HTTPLogRecord;
+use url::{split_url, registrable_domain};
+
+use super::{ObjectStats, TopMap, update_top};
+use super::topk::TopK;
+
+
+// Referers grouped by domain for external sites and by path for
+// self-referrals from the requested host
+pub struct RefererStats {
+    registrable_domains: bool,
+    pub domains: TopMap<String>,
+    pub external: ObjectStats,
+    pub internal: TopMap<String>,
+    pub internal_total: ObjectStats,
+}
+
+impl RefererStats {
+    pub fn new(capacity: uint, registrable_domains: bool) -> RefererStats {
+        RefererStats{
+            registrable_domains: registrable_domains,
+            domains: TopK::new(capacity),
+            external: ObjectStats::new(),
+            internal: TopK::new(capacity),
+            internal_total: ObjectStats::new(),
+            }
+    }
+
+    #[inline]
+    pub fn update(&mut self, record: &HTTPLogRecord) {
+        let (host, path) = match split_url(record.referer) {
+            Some((_, host, path)) => (host, path),
+            None => return
+        };
+        if host.eq_ignore_ascii_case(record.host) {
+            let path = match path.find(|c: char| c == '?' || c == '#') {
+                Some(pos) => path.slice_to(pos),
+                None => path
+            };
+            let path = if path.is_empty() { "/" } else { path };
+            self.internal_total.update(record);
+            update_top(&mut self.internal, path.to_string(), record);
+        } else {
+            let domain = referer_domain(host, self.registrable_domains);
+            self.external.update(record);
+            update_top(&mut self.domains, domain, record);
+        }
+    }
+}
+
+// Lower case domain of the referer host
+#[inline]
+pub fn referer_domain(host: &str, registrable: bool) -> String {
+    let domain = host.to_ascii_lower();
+    if registrable {
+        registrable_domain(domain.as_slice()).to_string()
+    } else {
+        domain
+    }
+}
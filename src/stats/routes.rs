@@ -0,0 +1,124 @@
+use regex::Regex;
+
+
+// Separator of the regex and the template in rules
+static RULE_SEPARATOR: &'static str = " = ";
+// Placeholders for the built-in rules
+static ID: &'static str = ":id";
+static HASH: &'static str = ":hash";
+// Minimal length of a hex segment to be treated as a hash
+static HASH_MIN_LENGTH: uint = 16;
+
+// Rules for normalization of paths into route templates like
+// /users/:id/orders/:id, query strings are dropped
+#[deriving(Clone)]
+pub struct Routes {
+    rules: Vec<(Regex, String)>,
+    builtin: bool,
+}
+
+impl Routes {
+    pub fn new(builtin: bool) -> Routes {
+        Routes{rules: Vec::new(), builtin: builtin}
+    }
+
+    // Add rule like "^/blog/[^/]+$ = /blog/:slug", the template can refer
+    // to the regex groups like $1
+    pub fn add(&mut self, rule: &str) -> Result<(), String> {
+        let (pattern, template) = match rule.find_str(RULE_SEPARATOR) {
+            Some(pos) => (rule.slice_to(pos),
+                          rule.slice_from(pos + RULE_SEPARATOR.len())),
+            None => return Err(format!("Template is required: {}", rule))
+        };
+        match Regex::new(pattern) {
+            Ok(regex) => self.rules.push((regex, template.to_string())),
+            Err(err) => return Err(format!("{}: {}", pattern, err))
+        }
+        Ok(())
+    }
+
+    // User rules are tried first in the order of addition
+    pub fn normalize(&self, path: &str) -> String {
+        let path = match path.find('?') {
+            Some(pos) => path.slice_to(pos),
+            None => path
+        };
+        for &(ref regex, ref template) in self.rules.iter() {
+            if regex.is_match(path) {
+                return regex.replace(path, template.as_slice());
+            }
+        }
+        if !self.builtin {
+            return path.to_string();
+        }
+        let segments: Vec<&str> = path.split('/')
+            .map(|segment| normalize_segment(segment))
+            .collect();
+        segments.connect("/")
+    }
+}
+
+#[inline]
+fn normalize_segment(segment: &str) -> &str {
+    if segment.is_empty() {
+        segment
+    } else if is_digits(segment, 10) || is_uuid(segment) {
+        ID
+    } else if segment.len() >= HASH_MIN_LENGTH && is_digits(segment, 16) {
+        HASH
+    } else {
+        segment
+    }
+}
+
+// UUID like 123e4567-e89b-12d3-a456-426655440000
+fn is_uuid(segment: &str) -> bool {
+    let groups: Vec<&str> = segment.split('-').collect();
+    groups.len() == 5
+        && groups.iter().zip([8u, 4, 4, 4, 12].iter())
+            .all(|(group, &len)| group.len() == len && is_digits(*group, 16))
+}
+
+#[inline]
+fn is_digits(segment: &str, radix: uint) -> bool {
+    segment.chars().all(|c| c.to_digit(radix).is_some())
+}
+
+/*
+ * Tests
+ */
+#[cfg(test)]
+mod test {
+    use super::Routes;
+
+    #[test]
+    fn test_builtin() {
+        let routes = Routes::new(true);
+        assert_eq!("/users/:id/orders/:id",
+                   routes.normalize("/users/123/orders/456?page=2").as_slice());
+        assert_eq!("/items/:id",
+                   routes.normalize("/items/123e4567-e89b-12d3-a456-\
+                                     426655440000").as_slice());
+        assert_eq!("/static/:hash/app.js",
+                   routes.normalize("/static/0123456789abcdef0123/app.js")
+                   .as_slice());
+        assert_eq!("/", routes.normalize("/").as_slice());
+        assert_eq!("/v2/cafe/", routes.normalize("/v2/cafe/").as_slice());
+    }
+
+    #[test]
+    fn test_rules() {
+        let mut routes = Routes::new(false);
+        assert!(routes.add("^/blog/[^/]+$ = /blog/:slug").is_ok());
+        assert!(routes.add("^/(en|de)/.* = /$1/*").is_ok());
+        assert!(routes.add("^/items;[a-z]+=[^/]+$ = /items/:filter").is_ok());
+        assert!(routes.add("/blog=/x").is_err());
+        assert!(routes.add("( = /x").is_err());
+        assert_eq!("/blog/:slug",
+                   routes.normalize("/blog/hello-world").as_slice());
+        assert_eq!("/de/*", routes.normalize("/de/about?x=1").as_slice());
+        assert_eq!("/users/1", routes.normalize("/users/1").as_slice());
+        assert_eq!("/items/:filter",
+                   routes.normalize("/items;color=red").as_slice());
+    }
+}
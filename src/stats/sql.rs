@@ -0,0 +1,378 @@
+use std::ascii::AsciiExt;
+
+use log::expr::Expr;
+use log::field::Field;
+
+
+// Clause keywords in the required order
+static CLAUSES: [&'static str, ..6] =
+    ["select", "from", "where", "group by", "order by", "limit"];
+// The only supported table
+static TABLE: &'static str = "log";
+
+#[deriving(Clone, PartialEq, Show)]
+pub enum Aggregate {
+    Count,
+    Sum(Field),
+    Avg(Field),
+    Min(Field),
+    Max(Field),
+    Percentile(Field, f64),
+}
+
+#[deriving(Clone, PartialEq, Show)]
+pub enum Column {
+    Value(Field),
+    Aggregate(Aggregate),
+}
+
+#[deriving(Clone, PartialEq, Show)]
+pub struct OrderBy {
+    // Index of the selected column
+    pub column: uint,
+    pub descending: bool,
+}
+
+// Query like SELECT path, count(*) FROM log WHERE status = 500
+// GROUP BY path ORDER BY 2 DESC LIMIT 20
+pub struct Query {
+    pub columns: Vec<Column>,
+    // Column titles as given in the query
+    pub titles: Vec<String>,
+    pub filter: Option<Expr>,
+    pub group_by: Vec<Field>,
+    pub order_by: Vec<OrderBy>,
+    pub limit: Option<uint>,
+}
+
+impl Query {
+    pub fn parse(sql: &str) -> Result<Query, String> {
+        let clauses = try!(split_clauses(sql));
+        let mut query = Query{
+            columns: Vec::new(),
+            titles: Vec::new(),
+            filter: None,
+            group_by: Vec::new(),
+            order_by: Vec::new(),
+            limit: None,
+            };
+        let mut from = None;
+        let mut order_by = None;
+        for &(clause, body) in clauses.iter() {
+            let body = body.trim();
+            if body.is_empty() {
+                return Err(format!("Empty {} clause", clause.to_ascii_upper()));
+            }
+            match clause {
+                "select" => {
+                    for item in body.split(',') {
+                        query.titles.push(item.trim().to_string());
+                        query.columns.push(try!(parse_column(item.trim())));
+                    }
+                },
+                "from" => from = Some(body),
+                "where" => query.filter = Some(try!(Expr::parse(body))),
+                "group by" => {
+                    for name in body.split(',') {
+                        query.group_by.push(try!(parse_field(name.trim())));
+                    }
+                },
+                "order by" => order_by = Some(body),
+                "limit" => match body.parse::<uint>() {
+                    Some(limit) => query.limit = Some(limit),
+                    None => return Err(format!("Invalid limit: {}", body))
+                },
+                _ => unreachable!()
+            }
+        }
+        match from {
+            Some(table) if table.eq_ignore_ascii_case(TABLE) => {},
+            Some(table) => return Err(format!("Unknown table: {}", table)),
+            None => return Err("Missing FROM clause".to_string())
+        }
+        for column in query.columns.iter() {
+            match *column {
+                Column::Value(field) if !query.group_by.contains(&field) =>
+                    return Err(format!("Field {} must be in GROUP BY",
+                                       field.name())),
+                _ => {}
+            }
+        }
+        match order_by {
+            Some(body) => {
+                for item in body.split(',') {
+                    let order = try!(parse_order_by(item.trim(),
+                                                    query.titles.as_slice()));
+                    query.order_by.push(order);
+                }
+            },
+            None => {}
+        }
+        Ok(query)
+    }
+}
+
+fn parse_field(name: &str) -> Result<Field, String> {
+    match Field::from_name(name) {
+        Some(field) => Ok(field),
+        None => Err(format!("Unknown field: {}", name))
+    }
+}
+
+// Field name or aggregate function like count(*), sum(sent_bytes) or
+// p95(request_time)
+fn parse_column(item: &str) -> Result<Column, String> {
+    let (function, argument) = match (item.find('('), item.ends_with(")")) {
+        (Some(pos), true) => (item.slice_to(pos).trim().to_ascii_lower(),
+                              item.slice(pos + 1, item.len() - 1).trim()),
+        (None, _) => return parse_field(item).map(|field| Column::Value(field)),
+        _ => return Err(format!("Invalid column: {}", item))
+    };
+    if function.as_slice() == "count" {
+        return if argument == "*" {
+            Ok(Column::Aggregate(Aggregate::Count))
+        } else {
+            Err(format!("Only count(*) is supported: {}", item))
+        };
+    }
+    let field = try!(parse_field(argument));
+    if !field.is_numeric() {
+        return Err(format!("Numeric field is required: {}", item));
+    }
+    let aggregate = match function.as_slice() {
+        "sum" => Aggregate::Sum(field),
+        "avg" => Aggregate::Avg(field),
+        "min" => Aggregate::Min(field),
+        "max" => Aggregate::Max(field),
+        name if name.starts_with("p") => {
+            match name.slice_from(1).parse::<f64>() {
+                Some(percent) if percent > 0.0 && percent <= 100.0 =>
+                    Aggregate::Percentile(field, percent),
+                _ => return Err(format!("Invalid percentile: {}", item))
+            }
+        },
+        _ => return Err(format!("Unknown function: {}", item))
+    };
+    Ok(Column::Aggregate(aggregate))
+}
+
+// Column number starting from 1 or column as given in SELECT with optional
+// ASC or DESC
+fn parse_order_by(item: &str, titles: &[String]) -> Result<OrderBy, String> {
+    let (column, descending) = match item.rfind(' ') {
+        Some(pos) if item.slice_from(pos + 1).eq_ignore_ascii_case("desc") =>
+            (item.slice_to(pos).trim(), true),
+        Some(pos) if item.slice_from(pos + 1).eq_ignore_ascii_case("asc") =>
+            (item.slice_to(pos).trim(), false),
+        _ => (item, false)
+    };
+    let index = match column.parse::<uint>() {
+        Some(n) if n > 0 && n <= titles.len() => Some(n - 1),
+        Some(_) => return Err(format!("Invalid column number: {}", column)),
+        None => titles.iter().position(|title|
+            normalize(title.as_slice()) == normalize(column))
+    };
+    match index {
+        Some(index) => Ok(OrderBy{column: index, descending: descending}),
+        None => Err(format!("Unknown ORDER BY column: {}", column))
+    }
+}
+
+// Lower case without whitespaces to compare columns
+fn normalize(column: &str) -> String {
+    column.chars().filter(|c| !c.is_whitespace()).collect::<String>()
+        .to_ascii_lower()
+}
+
+// Split query to clauses by keywords outside of quoted strings
+fn split_clauses(sql: &str) -> Result<Vec<(&'static str, &str)>, String> {
+    let mut clauses: Vec<(&'static str, &str)> = Vec::new();
+    let mut current: Option<(&'static str, uint)> = None;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut pos = 0;
+    let mut word_start = true;
+    while pos < sql.len() {
+        let range = sql.char_range_at(pos);
+        let c = range.ch;
+        if quote.is_some() {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if Some(c) == quote {
+                quote = None;
+            }
+        } else if c == '"' || c == '\'' {
+            quote = Some(c);
+        } else if word_start {
+            match find_clause(sql, pos) {
+                Some((clause, end)) => {
+                    match current {
+                        Some((name, start)) =>
+                            clauses.push((name, sql.slice(start, pos))),
+                        None if pos != 0 && !sql.slice_to(pos).trim()
+                                                 .is_empty() =>
+                            return Err(format!("Unexpected: {}",
+                                               sql.slice_to(pos).trim())),
+                        None => {}
+                    }
+                    let previous = clauses.last().map(|&(name, _)| name);
+                    try!(check_clause_order(previous, clause));
+                    current = Some((clause, end));
+                    pos = end;
+                    word_start = false;
+                    continue;
+                },
+                None => {}
+            }
+        }
+        word_start = c.is_whitespace();
+        pos = range.next;
+    }
+    if quote.is_some() {
+        return Err("Unterminated string".to_string());
+    }
+    match current {
+        Some((name, start)) => clauses.push((name, sql.slice_from(start))),
+        None => return Err("Missing SELECT clause".to_string())
+    }
+    match clauses[0] {
+        ("select", _) => Ok(clauses),
+        _ => Err("Query should start with SELECT".to_string())
+    }
+}
+
+// Clause keyword at the position with its end, words of keywords like
+// GROUP BY can be separated by any whitespace
+#[inline]
+fn find_clause(sql: &str, pos: uint) -> Option<(&'static str, uint)> {
+    for &clause in CLAUSES.iter() {
+        match match_words(sql, pos, clause) {
+            Some(end) if end == sql.len()
+                    || sql.char_at(end).is_whitespace() =>
+                return Some((clause, end)),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[inline]
+fn match_words(sql: &str, pos: uint, clause: &str) -> Option<uint> {
+    let mut end = pos;
+    for (i, word) in clause.split(' ').enumerate() {
+        if i > 0 {
+            let tail = sql.slice_from(end);
+            let skipped = tail.len() - tail.trim_left().len();
+            if skipped == 0 {
+                return None;
+            }
+            end += skipped;
+        }
+        let word_end = end + word.len();
+        if word_end > sql.len() || !sql.is_char_boundary(word_end)
+                || !sql.slice(end, word_end).eq_ignore_ascii_case(word) {
+            return None;
+        }
+        end = word_end;
+    }
+    Some(end)
+}
+
+#[inline]
+fn check_clause_order(previous: Option<&str>, clause: &'static str)
+        -> Result<(), String> {
+    let index = |name: &str| CLAUSES.iter().position(|c| *c == name);
+    match previous {
+        Some(previous) if index(previous) >= index(clause) =>
+            Err(format!("Unexpected {}", clause.to_ascii_upper())),
+        _ => Ok(())
+    }
+}
+
+/*
+ * Tests
+ */
+#[cfg(test)]
+mod test {
+    use log::field::Field;
+    use super::{Query, Column, Aggregate, OrderBy};
+
+    #[inline]
+    fn assert_error(exp: &str, sql: &str) {
+        match Query::parse(sql) {
+            Ok(_) => panic!("expected error for: {}", sql),
+            Err(err) => assert_eq!(exp, err.as_slice())
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let query = Query::parse(
+            "SELECT path, count(*), p95(request_time) FROM log \
+             WHERE status = 500 and path ^= '/api group by' \
+             GROUP BY path ORDER BY 2 DESC, path LIMIT 20").unwrap();
+        assert_eq!(vec![Column::Value(Field::Path),
+                        Column::Aggregate(Aggregate::Count),
+                        Column::Aggregate(
+                            Aggregate::Percentile(Field::RequestTime, 95.0))],
+                   query.columns);
+        assert_eq!("p95(request_time)", query.titles[2].as_slice());
+        assert!(query.filter.is_some());
+        assert_eq!(vec![Field::Path], query.group_by);
+        assert_eq!(vec![OrderBy{column: 1, descending: true},
+                        OrderBy{column: 0, descending: false}],
+                   query.order_by);
+        assert_eq!(Some(20), query.limit);
+    }
+
+    #[test]
+    fn test_clause_whitespace() {
+        let query = Query::parse("SELECT path, count(*) FROM log \
+                                  GROUP  BY path order\n\tby 2").unwrap();
+        assert_eq!(vec![Field::Path], query.group_by);
+        assert_eq!(vec![OrderBy{column: 1, descending: false}],
+                   query.order_by);
+    }
+
+    #[test]
+    fn test_parse_aggregates() {
+        let err = Query::parse("select Sum(sent_bytes), avg(request_time), \
+                                min(status), max(hour) from LOG \
+                                order by COUNT(*) asc").unwrap_err();
+        assert_eq!("Unknown ORDER BY column: COUNT(*)", err.as_slice());
+        let query = Query::parse("select count(*), sum(sent_bytes) from log \
+                                  order by sum( sent_bytes )").unwrap();
+        assert_eq!(vec![Column::Aggregate(Aggregate::Count),
+                        Column::Aggregate(Aggregate::Sum(Field::SentBytes))],
+                   query.columns);
+        assert_eq!(vec![OrderBy{column: 1, descending: false}],
+                   query.order_by);
+        assert!(query.group_by.is_empty());
+        assert!(query.limit.is_none());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_error("Missing SELECT clause", "");
+        assert_error("Unexpected: path", "path FROM log");
+        assert_error("Query should start with SELECT", "FROM log");
+        assert_error("Missing FROM clause", "SELECT count(*)");
+        assert_error("Unknown table: access", "SELECT count(*) FROM access");
+        assert_error("Unknown field: size", "SELECT size FROM log");
+        assert_error("Field path must be in GROUP BY",
+                     "SELECT path, count(*) FROM log");
+        assert_error("Numeric field is required: sum(path)",
+                     "SELECT sum(path) FROM log");
+        assert_error("Unknown function: median(request_time)",
+                     "SELECT median(request_time) FROM log");
+        assert_error("Unexpected WHERE",
+                     "SELECT count(*) FROM log LIMIT 1 WHERE status = 1");
+        assert_error("Empty WHERE clause", "SELECT count(*) FROM log WHERE");
+        assert_error("Invalid limit: ten",
+                     "SELECT count(*) FROM log LIMIT ten");
+        assert_error("Invalid column number: 3",
+                     "SELECT count(*) FROM log ORDER BY 3");
+    }
+}
@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+use time::Tm;
+
+use log::HTTPLogRecord;
+use timezone::timespec;
+
+use super::ObjectStats;
+use super::histogram::Histogram;
+
+
+pub struct TimeBucket {
+    pub stats: ObjectStats,
+    pub request_times: Histogram,
+}
+
+impl TimeBucket {
+    pub fn new() -> TimeBucket {
+        TimeBucket{stats: ObjectStats::new(), request_times: Histogram::new()}
+    }
+}
+
+// Requests in time buckets of the given resolution
+pub struct TimeSeries {
+    // Bucket size in seconds
+    pub resolution: i64,
+    // Buckets by start time in seconds of the local time
+    pub buckets: HashMap<i64, TimeBucket>,
+}
+
+impl TimeSeries {
+    pub fn new(resolution: i64) -> TimeSeries {
+        TimeSeries{resolution: resolution, buckets: HashMap::new()}
+    }
+
+    #[inline]
+    pub fn update(&mut self, time: &Tm, record: &HTTPLogRecord) {
+        // Buckets are aligned on the local time so days start at midnight
+        let local = timespec(time).sec + time.tm_utcoff as i64;
+        let start = local - modulo(local, self.resolution);
+        let bucket = match self.buckets.entry(start) {
+            Entry::Vacant(entry) => entry.set(TimeBucket::new()),
+            Entry::Occupied(entry) => entry.into_mut()
+        };
+        bucket.stats.update(record);
+        bucket.request_times.insert(record.request_time);
+    }
+}
+
+#[inline]
+fn modulo(n: i64, m: i64) -> i64 {
+    ((n % m) + m) % m
+}
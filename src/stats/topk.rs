@@ -0,0 +1,158 @@
+use std::collections::{HashMap, BTreeSet};
+use std::hash::Hash;
+
+
+struct Slot<K, V> {
+    key: K,
+    value: V,
+    // Number of updates since the key is monitored
+    count: u64,
+    // Maximum overestimation of the count
+    error: u64,
+}
+
+// Space-Saving heavy hitters algorithm: keeps at most capacity most frequent
+// keys with estimated counts. Zero capacity means exact unbounded tracking.
+pub struct TopK<K, V> {
+    capacity: uint,
+    slots: Vec<Slot<K, V>>,
+    index: HashMap<K, uint>,
+    // Slots ordered by estimated count, maintained only when bounded
+    order: BTreeSet<(u64, uint)>,
+}
+
+impl<K: Eq + Hash + Clone, V> TopK<K, V> {
+    pub fn new(capacity: uint) -> TopK<K, V> {
+        TopK{
+            capacity: capacity,
+            slots: Vec::new(),
+            index: HashMap::new(),
+            order: BTreeSet::new(),
+            }
+    }
+
+    #[inline]
+    pub fn is_bounded(&self) -> bool {
+        self.capacity != 0
+    }
+
+    // Count the key and return its value, the init value is used for new
+    // keys
+    #[inline]
+    pub fn update(&mut self, key: K, init: V) -> &mut V {
+        let slot = match self.index.get(&key).map(|&slot| slot) {
+            Some(slot) => slot,
+            None => self.insert(key, init)
+        };
+        self.count(slot)
+    }
+
+    // Like update but the init value is created only for new keys
+    #[inline]
+    pub fn update_with<F: FnOnce() -> V>(&mut self, key: K, init: F)
+            -> &mut V {
+        let slot = match self.index.get(&key).map(|&slot| slot) {
+            Some(slot) => slot,
+            None => self.insert(key, init())
+        };
+        self.count(slot)
+    }
+
+    #[inline]
+    fn count(&mut self, slot: uint) -> &mut V {
+        if self.is_bounded() {
+            let estimate = self.slots[slot].count + self.slots[slot].error;
+            self.order.remove(&(estimate, slot));
+            self.order.insert((estimate + 1, slot));
+        }
+        let entry = &mut self.slots[slot];
+        entry.count += 1;
+        &mut entry.value
+    }
+
+    // Keys with values and count errors
+    pub fn items(&self) -> Vec<(&K, &V, u64)> {
+        self.slots.iter().map(|slot| (&slot.key, &slot.value, slot.error))
+            .collect()
+    }
+
+    fn insert(&mut self, key: K, init: V) -> uint {
+        if !self.is_bounded() || self.slots.len() < self.capacity {
+            self.slots.push(Slot{key: key.clone(), value: init, count: 0,
+                                 error: 0});
+            let slot = self.slots.len() - 1;
+            self.index.insert(key, slot);
+            slot
+        } else {
+            // Replace the least frequent key which count becomes the error
+            let (min, slot) = *self.order.iter().next().unwrap();
+            self.order.remove(&(min, slot));
+            self.index.remove(&self.slots[slot].key);
+            self.slots[slot] = Slot{key: key.clone(), value: init, count: 0,
+                                    error: min};
+            self.index.insert(key, slot);
+            slot
+        }
+    }
+}
+
+/*
+ * Tests
+ */
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::TopK;
+
+    fn counts(top: &TopK<uint, u64>) -> HashMap<uint, (u64, u64)> {
+        top.items().iter().map(|&(key, value, error)| (*key, (*value, error)))
+            .collect()
+    }
+
+    #[test]
+    fn test_unbounded() {
+        let mut top = TopK::new(0);
+        for i in range(0u, 100) {
+            for _ in range(0, i % 3 + 1) {
+                *top.update(i, 0u64) += 1;
+            }
+        }
+        let counts = counts(&top);
+        assert_eq!(100, counts.len());
+        assert_eq!(Some(&(1, 0)), counts.get(&0));
+        assert_eq!(Some(&(3, 0)), counts.get(&5));
+    }
+
+    #[test]
+    fn test_bounded() {
+        let mut top = TopK::new(10);
+        for i in range(0u, 1000) {
+            // Heavy hitters 1 and 2 among unique keys, each key with count
+            // more than total / capacity is guaranteed to be monitored
+            *top.update(i % 2 + 1, 0u64) += 1;
+            *top.update(i + 10, 0u64) += 1;
+        }
+        let counts = counts(&top);
+        assert_eq!(10, counts.len());
+        let &(count, error) = counts.get(&1).unwrap();
+        assert!(count + error >= 500 && count <= 500);
+        let &(count, error) = counts.get(&2).unwrap();
+        assert!(count + error >= 500 && count <= 500);
+    }
+
+    #[test]
+    fn test_update_with() {
+        let mut top = TopK::new(2);
+        let mut created = 0u;
+        for &key in [1u, 1, 2, 1, 3].iter() {
+            *top.update_with(key, || { created += 1; 0u64 }) += 1;
+        }
+        // Key 3 replaces the least frequent key 2
+        assert_eq!(3, created);
+        let counts = counts(&top);
+        assert_eq!(Some(&(3, 0)), counts.get(&1));
+        assert_eq!(Some(&(1, 1)), counts.get(&3));
+        assert_eq!(None, counts.get(&2));
+    }
+}
@@ -0,0 +1,55 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+use log::HTTPLogRecord;
+
+use super::ObjectStats;
+
+
+pub struct PathNode {
+    pub stats: ObjectStats,
+    // Subdirectories by name
+    pub children: HashMap<String, PathNode>,
+}
+
+impl PathNode {
+    fn new() -> PathNode {
+        PathNode{stats: ObjectStats::new(), children: HashMap::new()}
+    }
+}
+
+// Stats rolled up by directory prefixes like /, /api/ and /api/v2/ up to
+// the given depth
+pub struct PathTree {
+    pub depth: uint,
+    pub root: PathNode,
+}
+
+impl PathTree {
+    pub fn new(depth: uint) -> PathTree {
+        PathTree{depth: depth, root: PathNode::new()}
+    }
+
+    #[inline]
+    pub fn update(&mut self, record: &HTTPLogRecord) {
+        let path = match record.path.find('?') {
+            Some(pos) => record.path.slice_to(pos),
+            None => record.path
+        };
+        let mut node = &mut self.root;
+        node.stats.update(record);
+        // Skip the empty segment before the leading slash and the last
+        // segment which is a file name or empty for directories
+        let segments: Vec<&str> = path.split('/').skip(1).collect();
+        let directories = cmp::max(segments.len(), 1) - 1;
+        for segment in segments.iter().take(directories).take(self.depth) {
+            let parent = node;
+            node = match parent.children.entry(segment.to_string()) {
+                Entry::Vacant(entry) => entry.set(PathNode::new()),
+                Entry::Occupied(entry) => entry.into_mut()
+            };
+            node.stats.update(record);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+use log::HTTPLogRecord;
+
+use super::hll::HyperLogLog;
+use super::topk::TopK;
+
+
+// Number of the most requested paths to count unique clients for if paths
+// aren't bounded, each path keeps its own estimator
+static UNIQUE_PATHS_CAPACITY: uint = 1000;
+
+pub struct UniqueStats {
+    pub requests: u64,
+    pub clients: HyperLogLog,
+}
+
+impl UniqueStats {
+    fn new(error: f64) -> UniqueStats {
+        UniqueStats{requests: 0, clients: HyperLogLog::new(error)}
+    }
+
+    #[inline]
+    fn update(&mut self, client: &str) {
+        self.requests += 1;
+        self.clients.insert(&client);
+    }
+}
+
+pub type UniqueMap = HashMap<String, UniqueStats>;
+
+// Approximate number of unique clients in total and per date, host and path
+pub struct UniqueClients {
+    pub error: f64,
+    pub total: HyperLogLog,
+    pub dates: UniqueMap,
+    pub hosts: UniqueMap,
+    pub paths: TopK<String, UniqueStats>,
+}
+
+impl UniqueClients {
+    pub fn new(error: f64, paths_capacity: uint) -> UniqueClients {
+        UniqueClients{
+            error: error,
+            total: HyperLogLog::new(error),
+            dates: HashMap::with_capacity(2),
+            hosts: HashMap::with_capacity(1),
+            paths: TopK::new(if paths_capacity == 0 {
+                UNIQUE_PATHS_CAPACITY
+            } else {
+                paths_capacity
+            }),
+            }
+    }
+
+    #[inline]
+    pub fn update_total(&mut self, client: &str) {
+        self.total.insert(&client);
+    }
+
+    #[inline]
+    pub fn update(&mut self, date: &str, record: &HTTPLogRecord) {
+        let client = record.remote_addr;
+        self.total.insert(&client);
+        update(&mut self.dates, date, client, self.error);
+        update(&mut self.hosts, record.host, client, self.error);
+        self.paths.update(record.path.to_string(), UniqueStats::new(self.error))
+            .update(client);
+    }
+}
+
+#[inline]
+fn update(mapping: &mut UniqueMap, key: &str, client: &str, error: f64) {
+    let stats = match mapping.entry(key.to_string()) {
+        Entry::Vacant(entry) => entry.set(UniqueStats::new(error)),
+        Entry::Occupied(entry) => entry.into_mut()
+    };
+    stats.update(client);
+}
@@ -56,12 +56,25 @@ pub fn format_number(mut bytes: u64) -> String {
     bytes.to_string() + MODIFIERS[i]
 }
 
+pub fn format_percent(part: u64, total: u64) -> String {
+    if total == 0 {
+        return "-".to_string();
+    }
+    // Percent with one decimal digit rounded half up
+    let n = (part * 1000 + total / 2) / total;
+    let int_part = (n / 10).to_string();
+    match n % 10 {
+        0 => int_part + "%",
+        digit => int_part + "." + digit.to_string().as_slice() + "%"
+    }
+}
+
 /*
  * Tests
  */
 #[cfg(test)]
 mod test {
-    use super::{format_duration, format_bytes, format_number};
+    use super::{format_duration, format_bytes, format_number, format_percent};
 
     #[inline]
     fn assert_duration(exp: &str, input: u64) {
@@ -133,4 +146,23 @@ mod test {
         assert_number("1T", 1000 * 1000 * 1000 * 1000);
         assert_number("1000T", 1000 * 1000 * 1000 * 1000 * 1000);
     }
+
+    #[inline]
+    fn assert_percent(exp: &str, part: u64, total: u64) {
+        assert_eq!(exp, format_percent(part, total).as_slice());
+    }
+
+    #[test]
+    fn test_format_percent() {
+        assert_percent("-", 0, 0);
+        assert_percent("0%", 0, 10);
+        assert_percent("0%", 1, 10000);
+        assert_percent("0.1%", 1, 1000);
+        assert_percent("10%", 1, 10);
+        assert_percent("33.3%", 1, 3);
+        assert_percent("66.7%", 2, 3);
+        assert_percent("99.9%", 999, 1000);
+        assert_percent("100%", 9999, 10000);
+        assert_percent("100%", 10, 10);
+    }
 }
@@ -0,0 +1,125 @@
+use std::os;
+
+use time::{Tm, Timespec, at, at_utc};
+
+
+// System zone information database for named time zones
+static ZONEINFO_DIR: &'static str = "/usr/share/zoneinfo";
+
+extern {
+    fn tzset();
+}
+
+#[deriving(Clone)]
+pub enum TimeZone {
+    // Keep the offset of each log record
+    Original,
+    Utc,
+    // System local time zone, a named zone is set through TZ
+    Local(String),
+}
+
+impl TimeZone {
+    // Time zone by name: utc, local or a zone name like Europe/Berlin from
+    // the zone information database
+    pub fn from_name(name: &str) -> Option<TimeZone> {
+        match name {
+            "utc" | "UTC" => Some(TimeZone::Utc),
+            "local" => Some(TimeZone::Local(name.to_string())),
+            _ if name.starts_with("/") || name.contains("..") => None,
+            _ if Path::new(ZONEINFO_DIR).join(name).is_file() =>
+                Some(TimeZone::Local(name.to_string())),
+            _ => None
+        }
+    }
+
+    // Set a named zone as the process local time zone, should be called
+    // once before any time is parsed or converted
+    pub fn set_local(&self) {
+        match *self {
+            TimeZone::Local(ref name) if name.as_slice() != "local" => {
+                os::setenv("TZ", name.as_slice());
+                unsafe { tzset() };
+            },
+            _ => {}
+        }
+    }
+
+    #[inline]
+    pub fn convert(&self, time: &Tm) -> Tm {
+        match *self {
+            TimeZone::Original => time.clone(),
+            TimeZone::Utc => at_utc(timespec(time)),
+            TimeZone::Local(_) => at(timespec(time))
+        }
+    }
+
+    // Time for the wall clock time in the zone, the system local zone is
+    // used for the original offsets
+    pub fn to_timespec(&self, time: &Tm) -> Timespec {
+        let timespec = wall_clock(time);
+        match *self {
+            TimeZone::Utc => timespec,
+            TimeZone::Original | TimeZone::Local(_) => {
+                let offset = at(timespec).tm_utcoff as i64;
+                Timespec::new(timespec.sec - offset, timespec.nsec)
+            }
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match *self {
+            TimeZone::Original => "log".to_string(),
+            TimeZone::Utc => "UTC".to_string(),
+            TimeZone::Local(ref name) => name.clone()
+        }
+    }
+}
+
+// Absolute time of the time with its own UTC offset. Tm::to_timespec isn't
+// used for times with offsets since it goes through the process local time
+// zone ignoring the offset.
+#[inline]
+pub fn timespec(time: &Tm) -> Timespec {
+    let wall = wall_clock(time);
+    Timespec::new(wall.sec - time.tm_utcoff as i64, wall.nsec)
+}
+
+// Wall clock time as if it was UTC time
+#[inline]
+fn wall_clock(time: &Tm) -> Timespec {
+    let mut utc = time.clone();
+    utc.tm_utcoff = 0;
+    utc.to_timespec()
+}
+
+/*
+ * Tests
+ */
+#[cfg(test)]
+mod test {
+    use time::{Timespec, strptime};
+
+    use super::{TimeZone, timespec};
+
+    #[test]
+    fn test_from_name() {
+        assert!(TimeZone::from_name("utc").is_some());
+        assert!(TimeZone::from_name("local").is_some());
+        assert!(TimeZone::from_name("../../../etc/passwd").is_none());
+        assert!(TimeZone::from_name("/etc/localtime").is_none());
+        assert!(TimeZone::from_name("No/Such_Zone").is_none());
+        assert!(TimeZone::from_name("Europe").is_none());
+    }
+
+    #[test]
+    fn test_convert() {
+        // 2014-10-10 11:55:36 UTC
+        let time = strptime("10/Oct/2014:13:55:36 +0200",
+                            "%d/%b/%Y:%H:%M:%S %z").unwrap();
+        assert_eq!(Timespec::new(1412942136, 0), timespec(&time));
+        let utc = TimeZone::Utc.convert(&time);
+        assert_eq!((11, 0), (utc.tm_hour, utc.tm_utcoff));
+        assert_eq!(Timespec::new(1412942136, 0), timespec(&utc));
+    }
+}
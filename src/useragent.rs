@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::io::File;
+
+use regex::Regex;
+
+
+// Maximal number of cached classified user agents
+static CACHE_CAPACITY: uint = 10000;
+// Name for user agents not matched by any rule of the kind
+static UNKNOWN: &'static str = "Other";
+// Names matched in user agents of known crawlers like Googlebot, shared with
+// the bot detection
+pub static CRAWLERS: &'static str =
+    r"(?i)[a-z0-9_.-]*(bot|crawler|spider|slurp)";
+// Browser and device of crawlers
+static BOT_BROWSER: &'static str = "Bot";
+static BOT_DEVICE: &'static str = "bot";
+
+// Rules in the format "kind | name | regex", the first matched rule of
+// each kind wins and the name can refer to the regex groups like $1
+static DEFAULT_RULES: &'static str = "
+browser | Edge $1 | Edg(?:e|A|iOS)?/(\\d+)
+browser | Opera $1 | (?:OPR|Opera)/(\\d+)
+browser | Samsung Internet $1 | SamsungBrowser/(\\d+)
+browser | Yandex $1 | YaBrowser/(\\d+)
+browser | Chrome $1 | (?:Chrome|CriOS)/(\\d+)
+browser | Firefox $1 | (?:Firefox|FxiOS)/(\\d+)
+browser | IE $1 | MSIE (\\d+)
+browser | IE $1 | Trident/.*rv:(\\d+)
+browser | Safari $1 | Version/(\\d+).*Safari/
+browser | curl $1 | ^curl/(\\d+)
+browser | Wget $1 | ^Wget/(\\d+)
+browser | Python Requests $1 | python-requests/(\\d+)
+os | Windows 10 | Windows NT 10\\.0
+os | Windows 8.1 | Windows NT 6\\.3
+os | Windows 8 | Windows NT 6\\.2
+os | Windows 7 | Windows NT 6\\.1
+os | Windows | Windows
+os | iOS $1 | (?:iPhone|iPad|iPod).* OS (\\d+)_
+os | Android $1 | Android (\\d+)
+os | Android | Android
+os | macOS $1.$2 | Mac OS X (\\d+)[_.](\\d+)
+os | Chrome OS | CrOS
+os | Linux | Linux
+device | tablet | iPad|Tablet|Kindle|Silk
+device | mobile | Mobi|iPhone|iPod|Windows Phone
+device | tablet | Android
+device | desktop | Windows|Macintosh|X11|CrOS
+";
+
+#[deriving(Clone, PartialEq, Show)]
+enum Kind {
+    Browser,
+    Os,
+    Device,
+}
+
+#[deriving(Clone)]
+struct Rule {
+    kind: Kind,
+    name: String,
+    regex: Regex,
+}
+
+#[deriving(Clone, PartialEq, Show)]
+pub struct UserAgent {
+    // Browser family with major version
+    pub browser: String,
+    pub os: String,
+    // Device type like desktop, mobile, tablet or bot
+    pub device: String,
+}
+
+// User agent classifier with a cache of recently classified user agents,
+// crawlers are classified before the rules
+#[deriving(Clone)]
+pub struct Classifier {
+    rules: Vec<Rule>,
+    crawlers: Regex,
+    cache: HashMap<String, UserAgent>,
+}
+
+impl Classifier {
+    pub fn new() -> Classifier {
+        Classifier::parse(DEFAULT_RULES).unwrap()
+    }
+
+    pub fn from_file(path: &Path) -> Result<Classifier, String> {
+        match File::open(path).read_to_string() {
+            Ok(rules) => Classifier::parse(rules.as_slice()),
+            Err(err) => Err(err.to_string())
+        }
+    }
+
+    pub fn parse(rules: &str) -> Result<Classifier, String> {
+        let mut classifier = Classifier{
+            rules: Vec::new(),
+            crawlers: Regex::new(CRAWLERS).unwrap(),
+            cache: HashMap::new(),
+            };
+        for (i, line) in rules.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("#") {
+                continue;
+            }
+            match parse_rule(line) {
+                Ok(rule) => classifier.rules.push(rule),
+                Err(err) => return Err(format!("line {}: {}", i + 1, err))
+            }
+        }
+        Ok(classifier)
+    }
+
+    #[inline]
+    pub fn classify(&mut self, user_agent: &str) -> &UserAgent {
+        if self.cache.len() >= CACHE_CAPACITY {
+            self.cache.clear();
+        }
+        let rules = self.rules.as_slice();
+        let crawlers = &self.crawlers;
+        match self.cache.entry(user_agent.to_string()) {
+            Entry::Vacant(entry) => {
+                // Cached user agents don't need the crawlers check
+                let is_crawler = crawlers.is_match(user_agent);
+                entry.set(UserAgent{
+                    browser: if is_crawler {
+                        BOT_BROWSER.to_string()
+                    } else {
+                        classify(rules, Kind::Browser, user_agent)
+                    },
+                    os: classify(rules, Kind::Os, user_agent),
+                    device: if is_crawler {
+                        BOT_DEVICE.to_string()
+                    } else {
+                        classify(rules, Kind::Device, user_agent)
+                    },
+                    })
+            },
+            Entry::Occupied(entry) => entry.into_mut()
+        }
+    }
+}
+
+fn parse_rule(line: &str) -> Result<Rule, String> {
+    let parts: Vec<&str> = line.splitn(2, '|').map(|part| part.trim())
+        .collect();
+    if parts.len() != 3 || parts.iter().any(|part| part.is_empty()) {
+        return Err(format!("expected \"kind | name | regex\": {}", line));
+    }
+    let kind = match parts[0] {
+        "browser" => Kind::Browser,
+        "os" => Kind::Os,
+        "device" => Kind::Device,
+        kind => return Err(format!("unknown kind: {}", kind))
+    };
+    match Regex::new(parts[2]) {
+        Ok(regex) => Ok(Rule{
+            kind: kind,
+            name: parts[1].to_string(),
+            regex: regex,
+            }),
+        Err(err) => Err(format!("{}: {}", parts[2], err))
+    }
+}
+
+#[inline]
+fn classify(rules: &[Rule], kind: Kind, user_agent: &str) -> String {
+    for rule in rules.iter().filter(|rule| rule.kind == kind) {
+        match rule.regex.captures(user_agent) {
+            Some(captures) => return captures.expand(rule.name.as_slice()),
+            None => {}
+        }
+    }
+    UNKNOWN.to_string()
+}
+
+/*
+ * Tests
+ */
+#[cfg(test)]
+mod test {
+    use super::{Classifier, UserAgent};
+
+    fn user_agent(browser: &str, os: &str, device: &str) -> UserAgent {
+        UserAgent{
+            browser: browser.to_string(),
+            os: os.to_string(),
+            device: device.to_string(),
+            }
+    }
+
+    #[test]
+    fn test_default_rules() {
+        let mut classifier = Classifier::new();
+        assert_eq!(user_agent("Chrome 38", "Windows 7", "desktop"),
+                   *classifier.classify(
+                       "Mozilla/5.0 (Windows NT 6.1; WOW64) \
+                        AppleWebKit/537.36 (KHTML, like Gecko) \
+                        Chrome/38.0.2125.104 Safari/537.36"));
+        assert_eq!(user_agent("Safari 8", "iOS 8", "mobile"),
+                   *classifier.classify(
+                       "Mozilla/5.0 (iPhone; CPU iPhone OS 8_0 like Mac OS X) \
+                        AppleWebKit/600.1.4 (KHTML, like Gecko) Version/8.0 \
+                        Mobile/12A365 Safari/600.1.4"));
+        assert_eq!(user_agent("Firefox 33", "macOS 10.10", "desktop"),
+                   *classifier.classify(
+                       "Mozilla/5.0 (Macintosh; Intel Mac OS X 10.10; \
+                        rv:33.0) Gecko/20100101 Firefox/33.0"));
+        assert_eq!(user_agent("Bot", "Other", "bot"),
+                   *classifier.classify(
+                       "Mozilla/5.0 (compatible; Googlebot/2.1; \
+                        +http://www.google.com/bot.html)"));
+        assert_eq!(user_agent("Chrome 38", "Android 4", "tablet"),
+                   *classifier.classify(
+                       "Mozilla/5.0 (Linux; Android 4.4.2; Nexus 7 \
+                        Build/KOT49H) AppleWebKit/537.36 (KHTML, like Gecko) \
+                        Chrome/38.0.2125.102 Safari/537.36"));
+        assert_eq!(user_agent("Chrome 38", "Android 4", "mobile"),
+                   *classifier.classify(
+                       "Mozilla/5.0 (Linux; Android 4.4.4; Nexus 5 \
+                        Build/KTU84P) AppleWebKit/537.36 (KHTML, like Gecko) \
+                        Chrome/38.0.2125.102 Mobile Safari/537.36"));
+        assert_eq!(user_agent("Other", "Other", "Other"),
+                   *classifier.classify("-"));
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut classifier = Classifier::parse(
+            "# Comment\n\nbrowser | App $1 | ^MyApp/(\\d+)\n").unwrap();
+        assert_eq!(user_agent("App 2", "Other", "Other"),
+                   *classifier.classify("MyApp/2.1"));
+        assert!(Classifier::parse("browser | App").is_err());
+        assert!(Classifier::parse("engine | App | App").is_err());
+        assert!(Classifier::parse("os | App | (").is_err());
+    }
+}